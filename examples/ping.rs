@@ -4,10 +4,14 @@ use futures::TryStreamExt;
 async fn main() {
     let token = std::env::var("DISCORD_TOKEN").expect("Missing DISCORD_TOKEN");
 
-    let (client, stream) = noob::Client::connect(&token)
-        .await
-        .expect("Failed to connect to Discord");
+    let (client, stream) = noob::Client::connect_with_intents(
+        &token,
+        noob::Intents::non_privileged() | noob::Intents::MESSAGE_CONTENT,
+    )
+    .await
+    .expect("Failed to connect to Discord");
     let client = std::sync::Arc::new(client);
+    let gateway = stream.handle();
     let res = stream
         .try_for_each(move |evt| {
             println!("event: {:?}", evt);
@@ -15,9 +19,13 @@ async fn main() {
                 println!("msg! {}", msg.content);
                 if msg.content == "ping" {
                     let client = client.clone();
+                    let reply = match gateway.latency() {
+                        Some(latency) => format!("pong ({}ms)", latency.as_millis()),
+                        None => "pong".to_owned(),
+                    };
                     tokio::spawn(async move {
                         let res = client
-                            .send_message(&noob::MessageBuilder::new("pong"), &msg.channel_id)
+                            .send_message(&noob::MessageBuilder::new(&reply), &msg.channel_id)
                             .await;
                         if let Err(e) = res {
                             eprintln!("{:?}", e);
@@ -0,0 +1,14 @@
+#[tokio::main]
+async fn main() {
+    let token = std::env::var("DISCORD_TOKEN").expect("Missing DISCORD_TOKEN");
+    let channel = std::env::var("CHANNEL_ID").expect("Missing CHANNEL_ID");
+
+    let client = noob::Client::new(&token);
+    client
+        .send_message(
+            &noob::MessageBuilder::new("Hello from a cron job"),
+            &channel,
+        )
+        .await
+        .expect("Failed to send message");
+}
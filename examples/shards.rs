@@ -0,0 +1,18 @@
+use futures::StreamExt;
+
+#[tokio::main]
+async fn main() {
+    let token = std::env::var("DISCORD_TOKEN").expect("Missing DISCORD_TOKEN");
+
+    let client = std::sync::Arc::new(noob::Client::new(&token));
+    let mut shards = noob::ShardManager::connect(client)
+        .await
+        .expect("Failed to connect to Discord");
+
+    while let Some((shard_id, evt)) = shards.next().await {
+        match evt {
+            Ok(evt) => println!("shard {}: {:?}", shard_id, evt),
+            Err(err) => eprintln!("shard {}: {:?}", shard_id, err),
+        }
+    }
+}
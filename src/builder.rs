@@ -33,19 +33,15 @@ impl<'a> MessageBuilder<'a> {
         struct MessageCreateBody<'a> {
             content: &'a str,
             channel: &'a str,
-            embed: Option<&'a EmbedBuilder<'a>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            embeds: Option<[&'a EmbedBuilder<'a>; 1]>,
         }
         serde_json::to_string(&MessageCreateBody {
             content: self.content,
             channel,
-            embed: self.embed,
-        })
-        .map_err(|e| {
-            Error::Other(format!(
-                "Failed to serialize message creation body: {:?}",
-                e
-            ))
+            embeds: self.embed.map(|embed| [embed]),
         })
+        .map_err(Error::Serialization)
     }
 }
 
@@ -0,0 +1,255 @@
+use super::encoding::Encoding;
+use super::{stream, Client, GatewayConfig, API_BASE};
+use crate::{Error, Intents, Presence};
+use std::time::Duration;
+
+/// Default upper bound for the delay between gateway reconnect attempts
+const DEFAULT_MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Object used to configure a [`Client`] before connecting
+pub struct ClientBuilder {
+    token: String,
+    api_base: String,
+    gateway_url: Option<String>,
+    tls: bool,
+    intents: Intents,
+    timeout: Option<Duration>,
+    user_agent_suffix: Option<String>,
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle_per_host: usize,
+    max_reconnect_delay: Duration,
+    shard: Option<[u64; 2]>,
+    presence: Option<Presence>,
+    compress: bool,
+    encoding: Encoding,
+}
+
+impl ClientBuilder {
+    /// Create a new ClientBuilder for a bot token
+    pub fn new(token: &str) -> Self {
+        Self {
+            token: token.to_owned(),
+            api_base: API_BASE.to_owned(),
+            gateway_url: None,
+            tls: true,
+            intents: Intents::non_privileged(),
+            timeout: None,
+            user_agent_suffix: None,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            pool_max_idle_per_host: usize::MAX,
+            max_reconnect_delay: DEFAULT_MAX_RECONNECT_DELAY,
+            shard: None,
+            presence: None,
+            compress: false,
+            encoding: Encoding::Json,
+        }
+    }
+
+    /// Set the base URL used for REST requests, defaults to `https://discord.com/api/v10`
+    pub fn set_api_base(&mut self, api_base: &str) {
+        self.api_base = api_base.trim_end_matches('/').to_owned();
+    }
+
+    /// Set the base URL used for REST requests, defaults to `https://discord.com/api/v10`
+    pub fn with_api_base(mut self, api_base: &str) -> Self {
+        self.set_api_base(api_base);
+        self
+    }
+
+    /// Set the gateway URL to connect to, instead of requesting one from `/gateway/bot`
+    pub fn set_gateway_url(&mut self, gateway_url: &str) {
+        self.gateway_url = Some(gateway_url.to_owned());
+    }
+
+    /// Set the gateway URL to connect to, instead of requesting one from `/gateway/bot`
+    pub fn with_gateway_url(mut self, gateway_url: &str) -> Self {
+        self.set_gateway_url(gateway_url);
+        self
+    }
+
+    /// Set whether connections must use TLS, defaults to `true`.
+    ///
+    /// Disable this to use plain `http://` and `ws://` URLs, e.g. for a local mock server.
+    pub fn set_tls(&mut self, tls: bool) {
+        self.tls = tls;
+    }
+
+    /// Set whether connections must use TLS, defaults to `true`.
+    ///
+    /// Disable this to use plain `http://` and `ws://` URLs, e.g. for a local mock server.
+    pub fn with_tls(mut self, tls: bool) -> Self {
+        self.set_tls(tls);
+        self
+    }
+
+    /// Set the gateway intents to use, defaults to [`Intents::non_privileged`]
+    pub fn set_intents(&mut self, intents: Intents) {
+        self.intents = intents;
+    }
+
+    /// Set the gateway intents to use, defaults to [`Intents::non_privileged`]
+    pub fn with_intents(mut self, intents: Intents) -> Self {
+        self.set_intents(intents);
+        self
+    }
+
+    /// Set how long to wait for a response to a REST request, defaults to no limit.
+    ///
+    /// Requests rate limited for longer than this fail with [`Error::RateLimited`].
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// Set how long to wait for a response to a REST request, defaults to no limit.
+    ///
+    /// Requests rate limited for longer than this fail with [`Error::RateLimited`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Set text to append to the `DiscordBot (url, version)` User-Agent
+    pub fn set_user_agent_suffix(&mut self, suffix: &str) {
+        self.user_agent_suffix = Some(suffix.to_owned());
+    }
+
+    /// Set text to append to the `DiscordBot (url, version)` User-Agent
+    pub fn with_user_agent_suffix(mut self, suffix: &str) -> Self {
+        self.set_user_agent_suffix(suffix);
+        self
+    }
+
+    /// Set how long idle HTTP connections are kept open, `None` to keep them indefinitely.
+    /// Defaults to 90 seconds.
+    pub fn set_pool_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.pool_idle_timeout = timeout;
+    }
+
+    /// Set how long idle HTTP connections are kept open, `None` to keep them indefinitely.
+    /// Defaults to 90 seconds.
+    pub fn with_pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.set_pool_idle_timeout(timeout);
+        self
+    }
+
+    /// Set the maximum number of idle HTTP connections kept open, defaults to no limit
+    pub fn set_pool_max_idle(&mut self, max: usize) {
+        self.pool_max_idle_per_host = max;
+    }
+
+    /// Set the maximum number of idle HTTP connections kept open, defaults to no limit
+    pub fn with_pool_max_idle(mut self, max: usize) -> Self {
+        self.set_pool_max_idle(max);
+        self
+    }
+
+    /// Set the maximum delay between gateway reconnect attempts, defaults to 60 seconds.
+    ///
+    /// Consecutive failed attempts back off exponentially up to this limit.
+    pub fn set_max_reconnect_delay(&mut self, delay: Duration) {
+        self.max_reconnect_delay = delay;
+    }
+
+    /// Set the maximum delay between gateway reconnect attempts, defaults to 60 seconds.
+    ///
+    /// Consecutive failed attempts back off exponentially up to this limit.
+    pub fn with_max_reconnect_delay(mut self, delay: Duration) -> Self {
+        self.set_max_reconnect_delay(delay);
+        self
+    }
+
+    /// Set the shard to connect as, for bots that need
+    /// [sharding](https://discord.com/developers/docs/topics/gateway#sharding)
+    pub fn set_shard(&mut self, shard_id: u64, shard_count: u64) {
+        self.shard = Some([shard_id, shard_count]);
+    }
+
+    /// Set the shard to connect as, for bots that need
+    /// [sharding](https://discord.com/developers/docs/topics/gateway#sharding)
+    pub fn with_shard(mut self, shard_id: u64, shard_count: u64) -> Self {
+        self.set_shard(shard_id, shard_count);
+        self
+    }
+
+    /// Set the presence to show when connecting to the gateway
+    pub fn set_presence(&mut self, presence: Presence) {
+        self.presence = Some(presence);
+    }
+
+    /// Set the presence to show when connecting to the gateway
+    pub fn with_presence(mut self, presence: Presence) -> Self {
+        self.set_presence(presence);
+        self
+    }
+
+    /// Set whether to use zlib-stream transport compression for the gateway, defaults to `false`
+    pub fn set_compression(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    /// Set whether to use zlib-stream transport compression for the gateway, defaults to `false`
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.set_compression(compress);
+        self
+    }
+
+    /// Set whether to use ETF instead of JSON for gateway payloads, defaults to `false`
+    #[cfg(feature = "etf")]
+    pub fn set_etf(&mut self, etf: bool) {
+        self.encoding = if etf { Encoding::Etf } else { Encoding::Json };
+    }
+
+    /// Set whether to use ETF instead of JSON for gateway payloads, defaults to `false`
+    #[cfg(feature = "etf")]
+    pub fn with_etf(mut self, etf: bool) -> Self {
+        self.set_etf(etf);
+        self
+    }
+
+    /// Create a REST-only client, without connecting to the gateway
+    pub fn build(&self) -> Client {
+        let mut connector = hyper_tls::HttpsConnector::new();
+        connector.https_only(self.tls);
+        let http = hyper::Client::builder()
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .build(connector);
+
+        let mut user_agent = format!(
+            "DiscordBot ({}, {})",
+            env!("CARGO_PKG_REPOSITORY"),
+            env!("CARGO_PKG_VERSION")
+        );
+        if let Some(ref suffix) = self.user_agent_suffix {
+            user_agent.push(' ');
+            user_agent.push_str(suffix);
+        }
+
+        Client {
+            http_client: http,
+            auth_value: format!("Bot {}", self.token),
+            api_base: self.api_base.clone(),
+            user_agent,
+            timeout: self.timeout,
+            ratelimiter: Default::default(),
+            gateway_config: GatewayConfig {
+                gateway_url: self.gateway_url.clone(),
+                tls: self.tls,
+                intents: self.intents,
+                max_reconnect_delay: self.max_reconnect_delay,
+                shard: self.shard,
+                presence: self.presence.clone(),
+                compress: self.compress,
+                encoding: self.encoding,
+            },
+            identify_limiter: Default::default(),
+        }
+    }
+
+    /// Create the client and connect to the gateway
+    pub async fn connect(self) -> Result<(Client, stream::GatewayConnection), Error> {
+        let client = self.build();
+        let gateway = client.connect_gateway().await?;
+        Ok((client, gateway))
+    }
+}
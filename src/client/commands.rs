@@ -0,0 +1,100 @@
+use crate::Error;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of payloads the gateway accepts per [`SEND_LIMIT_PERIOD`]
+const SEND_LIMIT: usize = 120;
+const SEND_LIMIT_PERIOD: Duration = Duration::from_secs(60);
+
+/// A gateway command waiting to be sent
+pub(crate) struct Command {
+    pub payload: serde_json::Value,
+    /// Nonce of the member request this command starts, if any
+    pub member_request: Option<String>,
+    /// Notified once the command has been written to the connection
+    pub sent: tokio::sync::oneshot::Sender<Result<(), Error>>,
+}
+
+/// Tracks payloads sent on a connection to stay under the gateway's send limit
+pub(crate) struct SendLimiter {
+    sent: VecDeque<Instant>,
+    /// Sends per period kept free for heartbeats
+    reserved: usize,
+}
+
+impl SendLimiter {
+    pub fn new(heartbeat_interval: Duration) -> Self {
+        // one per scheduled heartbeat, rounded up, plus one for a heartbeat requested by the gateway
+        let heartbeats = SEND_LIMIT_PERIOD.as_millis() / heartbeat_interval.as_millis().max(1);
+        Self {
+            sent: VecDeque::new(),
+            reserved: (heartbeats as usize + 2).min(SEND_LIMIT - 1),
+        }
+    }
+
+    /// Record that a payload was sent
+    pub fn record(&mut self) {
+        self.sent.push_back(Instant::now());
+    }
+
+    /// Earliest time a command may be sent without eating into the heartbeat reserve
+    pub fn next_command_slot(&mut self) -> Instant {
+        let now = Instant::now();
+        while let Some(&sent) = self.sent.front() {
+            if sent + SEND_LIMIT_PERIOD > now {
+                break;
+            }
+            self.sent.pop_front();
+        }
+
+        let limit = SEND_LIMIT - self.reserved;
+        if self.sent.len() < limit {
+            now
+        } else {
+            self.sent[self.sent.len() - limit] + SEND_LIMIT_PERIOD
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserves_room_for_heartbeats() {
+        assert_eq!(SendLimiter::new(Duration::from_millis(41250)).reserved, 3);
+        assert_eq!(SendLimiter::new(Duration::from_secs(20)).reserved, 5);
+        assert_eq!(
+            SendLimiter::new(Duration::from_millis(1)).reserved,
+            SEND_LIMIT - 1
+        );
+    }
+
+    #[test]
+    fn waits_for_oldest_send_in_window() {
+        let mut limiter = SendLimiter::new(Duration::from_millis(41250));
+        let limit = SEND_LIMIT - limiter.reserved;
+        let start = Instant::now();
+        let sends: Vec<_> = (0..limit as u64 + 1)
+            .map(|i| start + Duration::from_millis(i))
+            .collect();
+
+        limiter.sent.extend(&sends[..limit - 1]);
+        assert!(limiter.next_command_slot() <= Instant::now());
+
+        limiter.sent.push_back(sends[limit - 1]);
+        assert_eq!(limiter.next_command_slot(), sends[0] + SEND_LIMIT_PERIOD);
+
+        limiter.sent.push_back(sends[limit]);
+        assert_eq!(limiter.next_command_slot(), sends[1] + SEND_LIMIT_PERIOD);
+    }
+
+    #[test]
+    fn forgets_sends_outside_window() {
+        let mut limiter = SendLimiter::new(Duration::from_millis(41250));
+        let old = Instant::now() - SEND_LIMIT_PERIOD - Duration::from_secs(1);
+        limiter.sent.extend(vec![old; SEND_LIMIT]);
+        assert!(limiter.next_command_slot() <= Instant::now());
+        assert!(limiter.sent.is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+use crate::Error;
+
+/// Trailer marking the end of each payload in a zlib-stream
+const ZLIB_SUFFIX: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Decompression context for a connection using `compress=zlib-stream`, shared by all its messages
+pub(crate) struct Inflater {
+    decompress: flate2::Decompress,
+    buffer: Vec<u8>,
+}
+
+impl Inflater {
+    pub fn new() -> Self {
+        Self {
+            decompress: flate2::Decompress::new(true),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Add a binary frame, returning the decompressed payload once a complete one has arrived
+    pub fn push(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.buffer.extend_from_slice(data);
+        if !self.buffer.ends_with(&ZLIB_SUFFIX) {
+            return Ok(None);
+        }
+
+        let mut output = Vec::with_capacity(self.buffer.len() * 4);
+        let mut offset = 0;
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(self.buffer.len() * 2);
+            }
+            let total_in = self.decompress.total_in();
+            let total_out = self.decompress.total_out();
+            self.decompress
+                .decompress_vec(
+                    &self.buffer[offset..],
+                    &mut output,
+                    flate2::FlushDecompress::Sync,
+                )
+                .map_err(|err| {
+                    Error::Other(format!("Failed to decompress gateway payload: {}", err))
+                })?;
+            offset += (self.decompress.total_in() - total_in) as usize;
+
+            let progressed =
+                self.decompress.total_in() != total_in || self.decompress.total_out() != total_out;
+            if !progressed || (offset == self.buffer.len() && output.len() < output.capacity()) {
+                break;
+            }
+        }
+        self.buffer.clear();
+
+        Ok(Some(output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn inflates_split_payloads_with_shared_context() {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut inflater = Inflater::new();
+
+        // large enough to need the output buffer to grow several times
+        let large: Vec<u8> = (0..200_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let payloads = [b"{\"op\":10}".to_vec(), large, b"{\"op\":11}".to_vec()];
+        for payload in &payloads {
+            encoder.write_all(payload).unwrap();
+            encoder.flush().unwrap();
+            let compressed = std::mem::take(encoder.get_mut());
+            assert!(compressed.ends_with(&ZLIB_SUFFIX));
+
+            let (first, rest) = compressed.split_at(compressed.len() / 2);
+            let (second, last) = rest.split_at(rest.len() - 2);
+            assert_eq!(inflater.push(first).unwrap(), None);
+            assert_eq!(inflater.push(second).unwrap(), None);
+            assert_eq!(inflater.push(last).unwrap().as_ref(), Some(payload));
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use crate::Error;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio_tungstenite::tungstenite;
+
+/// Payload encoding used on a gateway connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Json,
+    #[cfg(feature = "etf")]
+    Etf,
+}
+
+impl Encoding {
+    /// Value for the `encoding` query parameter
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Json => "json",
+            #[cfg(feature = "etf")]
+            Encoding::Etf => "etf",
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, payload: &T) -> Result<tungstenite::Message, Error> {
+        match self {
+            Encoding::Json => Ok(tungstenite::Message::Text(serde_json::to_string(payload)?)),
+            #[cfg(feature = "etf")]
+            Encoding::Etf => Ok(tungstenite::Message::Binary(super::etf::encode(
+                &serde_json::to_value(payload)?,
+            )?)),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, data: &[u8]) -> Result<T, Error> {
+        match self {
+            Encoding::Json => serde_json::from_slice(data).map_err(Error::Serialization),
+            #[cfg(feature = "etf")]
+            Encoding::Etf => {
+                serde_json::from_value(super::etf::decode(data)?).map_err(Error::Serialization)
+            }
+        }
+    }
+}
@@ -0,0 +1,381 @@
+//! [External Term Format](https://www.erlang.org/doc/apps/erts/erl_ext_dist.html) codec,
+//! converting to and from JSON values so the same payload types can be used for both encodings
+
+use crate::Error;
+use serde_json::Value;
+use std::convert::TryFrom;
+
+const FORMAT_VERSION: u8 = 131;
+
+const NEW_FLOAT_EXT: u8 = 70;
+const SMALL_INTEGER_EXT: u8 = 97;
+const INTEGER_EXT: u8 = 98;
+const FLOAT_EXT: u8 = 99;
+const ATOM_EXT: u8 = 100;
+const SMALL_TUPLE_EXT: u8 = 104;
+const LARGE_TUPLE_EXT: u8 = 105;
+const NIL_EXT: u8 = 106;
+const STRING_EXT: u8 = 107;
+const LIST_EXT: u8 = 108;
+const BINARY_EXT: u8 = 109;
+const SMALL_BIG_EXT: u8 = 110;
+const LARGE_BIG_EXT: u8 = 111;
+const SMALL_ATOM_EXT: u8 = 115;
+const MAP_EXT: u8 = 116;
+const ATOM_UTF8_EXT: u8 = 118;
+const SMALL_ATOM_UTF8_EXT: u8 = 119;
+
+fn invalid(msg: &str) -> Error {
+    Error::Other(format!("Invalid ETF payload: {}", msg))
+}
+
+/// Decode an ETF payload.
+///
+/// Big integers, which the gateway uses for snowflakes, are decoded as strings to match JSON.
+pub(crate) fn decode(data: &[u8]) -> Result<Value, Error> {
+    let mut decoder = Decoder { data };
+    if decoder.u8()? != FORMAT_VERSION {
+        return Err(invalid("unsupported format version"));
+    }
+    decoder.term()
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < len {
+            return Err(invalid("unexpected end of data"));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut out = [0; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<usize, Error> {
+        Ok(u16::from_be_bytes(self.array()?).into())
+    }
+
+    fn u32(&mut self) -> Result<usize, Error> {
+        Ok(u32::from_be_bytes(self.array()?) as usize)
+    }
+
+    fn term(&mut self) -> Result<Value, Error> {
+        match self.u8()? {
+            SMALL_INTEGER_EXT => Ok(self.u8()?.into()),
+            INTEGER_EXT => Ok(i32::from_be_bytes(self.array()?).into()),
+            NEW_FLOAT_EXT => Ok(float(f64::from_be_bytes(self.array()?))),
+            FLOAT_EXT => {
+                let text = std::str::from_utf8(self.take(31)?)
+                    .map_err(|_| invalid("float is not UTF-8"))?;
+                text.trim_end_matches('\0')
+                    .parse()
+                    .map(float)
+                    .map_err(|_| invalid("invalid float"))
+            }
+            ATOM_EXT | ATOM_UTF8_EXT => {
+                let len = self.u16()?;
+                self.atom(len)
+            }
+            SMALL_ATOM_EXT | SMALL_ATOM_UTF8_EXT => {
+                let len = self.u8()?.into();
+                self.atom(len)
+            }
+            SMALL_TUPLE_EXT => {
+                let len = self.u8()?.into();
+                self.terms(len)
+            }
+            LARGE_TUPLE_EXT => {
+                let len = self.u32()?;
+                self.terms(len)
+            }
+            NIL_EXT => Ok(Value::Array(Vec::new())),
+            STRING_EXT => {
+                let len = self.u16()?;
+                Ok(Value::Array(
+                    self.take(len)?.iter().map(|&byte| byte.into()).collect(),
+                ))
+            }
+            LIST_EXT => {
+                let len = self.u32()?;
+                let list = self.terms(len)?;
+                match self.term()? {
+                    Value::Array(ref tail) if tail.is_empty() => Ok(list),
+                    _ => Err(invalid("improper lists are not supported")),
+                }
+            }
+            BINARY_EXT => {
+                let len = self.u32()?;
+                String::from_utf8(self.take(len)?.to_vec())
+                    .map(Value::String)
+                    .map_err(|_| invalid("binary is not UTF-8"))
+            }
+            SMALL_BIG_EXT => {
+                let len = self.u8()?.into();
+                self.big(len)
+            }
+            LARGE_BIG_EXT => {
+                let len = self.u32()?;
+                self.big(len)
+            }
+            MAP_EXT => {
+                let len = self.u32()?;
+                let mut map = serde_json::Map::new();
+                for _ in 0..len {
+                    let key = match self.term()? {
+                        Value::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    map.insert(key, self.term()?);
+                }
+                Ok(Value::Object(map))
+            }
+            tag => Err(invalid(&format!("unsupported term type {}", tag))),
+        }
+    }
+
+    fn terms(&mut self, len: usize) -> Result<Value, Error> {
+        let mut out = Vec::with_capacity(len.min(self.data.len()));
+        for _ in 0..len {
+            out.push(self.term()?);
+        }
+        Ok(Value::Array(out))
+    }
+
+    fn atom(&mut self, len: usize) -> Result<Value, Error> {
+        let name =
+            std::str::from_utf8(self.take(len)?).map_err(|_| invalid("atom is not UTF-8"))?;
+        Ok(match name {
+            "nil" => Value::Null,
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            name => Value::String(name.to_owned()),
+        })
+    }
+
+    fn big(&mut self, len: usize) -> Result<Value, Error> {
+        let negative = self.u8()? != 0;
+        let digits = self.take(len)?;
+        if len > 16 {
+            return Err(invalid("integer is too large"));
+        }
+        let value = digits
+            .iter()
+            .rev()
+            .fold(0u128, |value, &digit| value << 8 | u128::from(digit));
+        Ok(Value::String(if negative {
+            format!("-{}", value)
+        } else {
+            value.to_string()
+        }))
+    }
+}
+
+fn float(value: f64) -> Value {
+    serde_json::Number::from_f64(value).map_or(Value::Null, Value::Number)
+}
+
+/// Encode a value as an ETF payload
+pub(crate) fn encode(value: &Value) -> Result<Vec<u8>, Error> {
+    let mut out = vec![FORMAT_VERSION];
+    encode_term(value, &mut out)?;
+    Ok(out)
+}
+
+fn encode_len(len: usize, out: &mut Vec<u8>) -> Result<(), Error> {
+    let len = u32::try_from(len).map_err(|_| invalid("value is too large"))?;
+    out.extend_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+fn encode_term(value: &Value, out: &mut Vec<u8>) -> Result<(), Error> {
+    match value {
+        Value::Null => encode_atom("nil", out),
+        Value::Bool(true) => encode_atom("true", out),
+        Value::Bool(false) => encode_atom("false", out),
+        Value::Number(number) => {
+            if let Some(number) = number.as_u64() {
+                encode_integer(number.into(), out);
+            } else if let Some(number) = number.as_i64() {
+                encode_integer(number.into(), out);
+            } else if let Some(number) = number.as_f64() {
+                out.push(NEW_FLOAT_EXT);
+                out.extend_from_slice(&number.to_be_bytes());
+            }
+        }
+        Value::String(string) => encode_binary(string, out)?,
+        Value::Array(items) => {
+            if !items.is_empty() {
+                out.push(LIST_EXT);
+                encode_len(items.len(), out)?;
+                for item in items {
+                    encode_term(item, out)?;
+                }
+            }
+            out.push(NIL_EXT);
+        }
+        Value::Object(map) => {
+            out.push(MAP_EXT);
+            encode_len(map.len(), out)?;
+            for (key, value) in map {
+                encode_binary(key, out)?;
+                encode_term(value, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn encode_atom(name: &str, out: &mut Vec<u8>) {
+    out.push(SMALL_ATOM_UTF8_EXT);
+    out.push(name.len() as u8);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn encode_binary(string: &str, out: &mut Vec<u8>) -> Result<(), Error> {
+    out.push(BINARY_EXT);
+    encode_len(string.len(), out)?;
+    out.extend_from_slice(string.as_bytes());
+    Ok(())
+}
+
+fn encode_integer(number: i128, out: &mut Vec<u8>) {
+    if let Ok(number) = u8::try_from(number) {
+        out.push(SMALL_INTEGER_EXT);
+        out.push(number);
+    } else if let Ok(number) = i32::try_from(number) {
+        out.push(INTEGER_EXT);
+        out.extend_from_slice(&number.to_be_bytes());
+    } else {
+        let mut magnitude = number.unsigned_abs();
+        let mut digits = Vec::new();
+        while magnitude > 0 {
+            digits.push(magnitude as u8);
+            magnitude >>= 8;
+        }
+        out.push(SMALL_BIG_EXT);
+        out.push(digits.len() as u8);
+        out.push((number < 0).into());
+        out.extend_from_slice(&digits);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn round_trip(value: Value) -> Value {
+        decode(&encode(&value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn round_trips_values() {
+        for value in [
+            json!(null),
+            json!(true),
+            json!(false),
+            json!(0),
+            json!(255),
+            json!(256),
+            json!(-1),
+            json!(i32::MAX),
+            json!(i32::MIN),
+            json!(1.5),
+            json!(-0.25),
+            json!([]),
+            json!([1, "two", [3.5, null]]),
+            json!({}),
+            json!({"op": 2, "d": {"token": "abc", "shard": [0, 1], "presence": null}}),
+        ] {
+            assert_eq!(round_trip(value.clone()), value);
+        }
+    }
+
+    #[test]
+    fn chooses_integer_encodings() {
+        assert_eq!(encode(&json!(255)).unwrap(), [131, SMALL_INTEGER_EXT, 255]);
+        assert_eq!(encode(&json!(256)).unwrap(), [131, INTEGER_EXT, 0, 0, 1, 0]);
+        assert_eq!(
+            encode(&json!(-1)).unwrap(),
+            [131, INTEGER_EXT, 255, 255, 255, 255]
+        );
+        assert_eq!(
+            encode(&json!(1u64 << 32)).unwrap(),
+            [131, SMALL_BIG_EXT, 5, 0, 0, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn decodes_big_integers_as_strings() {
+        assert_eq!(round_trip(json!(u64::MAX)), json!(u64::MAX.to_string()));
+        assert_eq!(round_trip(json!(i64::MIN)), json!(i64::MIN.to_string()));
+        assert_eq!(
+            round_trip(json!(-(1i64 << 40))),
+            json!((-(1i64 << 40)).to_string())
+        );
+
+        let snowflake: u64 = 175928847299117063;
+        let mut data = vec![131, SMALL_BIG_EXT, 8, 0];
+        data.extend_from_slice(&snowflake.to_le_bytes());
+        assert_eq!(decode(&data).unwrap(), json!("175928847299117063"));
+
+        let mut data = vec![131, LARGE_BIG_EXT, 0, 0, 0, 2, 1];
+        data.extend_from_slice(&[0x34, 0x12]);
+        assert_eq!(decode(&data).unwrap(), json!("-4660"));
+    }
+
+    #[test]
+    fn decodes_atoms_and_strings() {
+        assert_eq!(
+            decode(&[131, ATOM_EXT, 0, 3, b'n', b'i', b'l']).unwrap(),
+            json!(null)
+        );
+        assert_eq!(
+            decode(&[131, SMALL_ATOM_EXT, 2, b'o', b'k']).unwrap(),
+            json!("ok")
+        );
+        assert_eq!(
+            decode(&[131, STRING_EXT, 0, 2, 1, 2]).unwrap(),
+            json!([1, 2])
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let data = encode(&json!({"op": 1, "d": 1u64 << 40})).unwrap();
+        for len in 0..data.len() {
+            assert!(decode(&data[..len]).is_err(), "accepted {} bytes", len);
+        }
+        assert!(decode(&[130, NIL_EXT]).is_err());
+    }
+
+    #[test]
+    fn rejects_improper_lists() {
+        let data = [
+            131,
+            LIST_EXT,
+            0,
+            0,
+            0,
+            1,
+            SMALL_INTEGER_EXT,
+            1,
+            SMALL_INTEGER_EXT,
+            2,
+        ];
+        assert!(decode(&data).is_err());
+    }
+}
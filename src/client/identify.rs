@@ -0,0 +1,214 @@
+use crate::types::SessionStartLimit;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Minimum time between identifies in the same concurrency bucket
+const IDENTIFY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the session start limit resets
+const SESSION_LIMIT_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Schedules IDENTIFY payloads to respect the session start limit
+pub(crate) struct IdentifyLimiter {
+    state: Mutex<IdentifyState>,
+}
+
+struct IdentifyState {
+    max_concurrency: u64,
+    /// Earliest time the next identify may be sent, for each rate limit key
+    next_allowed: HashMap<u64, Instant>,
+    /// Remaining session starts, if known from `/gateway/bot`
+    budget: Option<SessionBudget>,
+}
+
+struct SessionBudget {
+    total: u64,
+    remaining: u64,
+    reset_at: Instant,
+}
+
+impl SessionBudget {
+    /// Start a new period if the limit has reset by `at`
+    fn refresh(&mut self, at: Instant) {
+        if at >= self.reset_at {
+            self.remaining = self.total;
+            self.reset_at = at + SESSION_LIMIT_PERIOD;
+        }
+    }
+}
+
+impl Default for IdentifyLimiter {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(IdentifyState {
+                max_concurrency: 1,
+                next_allowed: HashMap::new(),
+                budget: None,
+            }),
+        }
+    }
+}
+
+impl IdentifyLimiter {
+    /// Update the limits with data from `/gateway/bot`
+    pub fn update(&self, limit: &SessionStartLimit) {
+        let mut state = self.state.lock().unwrap();
+        state.max_concurrency = limit.max_concurrency.max(1);
+        state.budget = Some(SessionBudget {
+            total: limit.total,
+            remaining: limit.remaining,
+            reset_at: Instant::now() + Duration::from_millis(limit.reset_after),
+        });
+    }
+
+    /// Current session start limit, accounting for identifies sent since the last update
+    pub fn current(&self) -> Option<SessionStartLimit> {
+        let state = self.state.lock().unwrap();
+        state.budget.as_ref().map(|budget| SessionStartLimit {
+            total: budget.total,
+            remaining: budget.remaining,
+            reset_after: budget
+                .reset_at
+                .saturating_duration_since(Instant::now())
+                .as_millis() as u64,
+            max_concurrency: state.max_concurrency,
+        })
+    }
+
+    /// Wait until the given shard may identify
+    pub async fn wait(&self, shard_id: u64) {
+        let now = Instant::now();
+        let slot = self.reserve(shard_id, now);
+        tokio::time::sleep(slot - now).await;
+    }
+
+    /// Count an identify against the session start limit, once it has actually been sent
+    pub fn record(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(ref mut budget) = state.budget {
+            budget.refresh(Instant::now());
+            budget.remaining = budget.remaining.saturating_sub(1);
+        }
+    }
+
+    /// Pick the time the given shard may identify, and space the next one in its bucket after it
+    fn reserve(&self, shard_id: u64, now: Instant) -> Instant {
+        let mut state = self.state.lock().unwrap();
+        let key = shard_id % state.max_concurrency;
+        let mut slot = match state.next_allowed.get(&key) {
+            Some(&next) if next > now => next,
+            _ => now,
+        };
+
+        if let Some(ref mut budget) = state.budget {
+            budget.refresh(slot);
+            if budget.remaining == 0 {
+                eprintln!(
+                    "Session start limit exhausted, waiting {:?} to identify",
+                    budget.reset_at - now
+                );
+                slot = budget.reset_at;
+                budget.refresh(slot);
+            }
+        }
+
+        state.next_allowed.insert(key, slot + IDENTIFY_INTERVAL);
+        slot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(remaining: u64, max_concurrency: u64) -> IdentifyLimiter {
+        let limiter = IdentifyLimiter::default();
+        limiter.update(&SessionStartLimit {
+            total: 1000,
+            remaining,
+            reset_after: 60 * 60 * 1000,
+            max_concurrency,
+        });
+        limiter
+    }
+
+    fn remaining(limiter: &IdentifyLimiter) -> u64 {
+        limiter.current().unwrap().remaining
+    }
+
+    #[test]
+    fn spaces_identifies_per_bucket() {
+        let limiter = limiter(1000, 2);
+        let now = Instant::now();
+        assert_eq!(limiter.reserve(0, now), now);
+        assert_eq!(limiter.reserve(1, now), now);
+        assert_eq!(limiter.reserve(2, now), now + IDENTIFY_INTERVAL);
+        assert_eq!(limiter.reserve(5, now), now + IDENTIFY_INTERVAL);
+        assert_eq!(limiter.reserve(4, now), now + IDENTIFY_INTERVAL * 2);
+
+        let later = now + Duration::from_secs(60);
+        assert_eq!(limiter.reserve(0, later), later);
+    }
+
+    #[test]
+    fn counts_only_sent_identifies() {
+        let limiter = limiter(10, 1);
+        let now = Instant::now();
+        for i in 0..5 {
+            assert_eq!(limiter.reserve(0, now), now + IDENTIFY_INTERVAL * i);
+        }
+        assert_eq!(remaining(&limiter), 10);
+
+        limiter.record();
+        assert_eq!(remaining(&limiter), 9);
+    }
+
+    #[test]
+    fn waits_for_reset_when_exhausted() {
+        let limiter = limiter(1, 1);
+        let now = Instant::now();
+        let reset_at = limiter
+            .state
+            .lock()
+            .unwrap()
+            .budget
+            .as_ref()
+            .unwrap()
+            .reset_at;
+
+        assert_eq!(limiter.reserve(0, now), now);
+        limiter.record();
+        assert_eq!(remaining(&limiter), 0);
+
+        assert_eq!(limiter.reserve(0, now), reset_at);
+        let state = limiter.state.lock().unwrap();
+        let budget = state.budget.as_ref().unwrap();
+        assert_eq!(budget.remaining, budget.total);
+        assert_eq!(budget.reset_at, reset_at + SESSION_LIMIT_PERIOD);
+    }
+
+    #[test]
+    fn refills_at_reset() {
+        let limiter = limiter(3, 1);
+        let reset_at = limiter
+            .state
+            .lock()
+            .unwrap()
+            .budget
+            .as_ref()
+            .unwrap()
+            .reset_at;
+
+        let before = reset_at - Duration::from_secs(1);
+        assert_eq!(limiter.reserve(0, before), before);
+        assert_eq!(remaining(&limiter), 3);
+
+        let after = reset_at + IDENTIFY_INTERVAL;
+        assert_eq!(limiter.reserve(0, after), after);
+        let state = limiter.state.lock().unwrap();
+        let budget = state.budget.as_ref().unwrap();
+        assert_eq!(budget.remaining, 1000);
+        assert_eq!(budget.reset_at, after + SESSION_LIMIT_PERIOD);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::types::{Member, Snowflake};
+use crate::{Error, UserPresence};
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+
+/// Which members to request with [`GatewayHandle::request_guild_members`](super::GatewayHandle::request_guild_members)
+pub enum MemberQuery<'a> {
+    /// Members whose username starts with `query`, or all members if it is empty.
+    ///
+    /// A `limit` of 0 means no limit, which requires [`Intents::GUILD_MEMBERS`](crate::Intents::GUILD_MEMBERS)
+    /// when `query` is empty.
+    Prefix {
+        /// Username prefix to match
+        query: &'a str,
+        /// Maximum number of members to return
+        limit: u32,
+    },
+    /// Specific members by user ID, up to 100
+    UserIds(&'a [Snowflake]),
+}
+
+#[derive(Debug, Default)]
+/// Members received for a request, reassembled from all of its chunks
+pub struct GuildMembers {
+    /// Members matching the request
+    pub members: Vec<Member>,
+    /// Requested user IDs that are not members of the guild
+    pub not_found: Vec<Snowflake>,
+    /// Presences of the members, if requested
+    pub presences: Vec<UserPresence>,
+}
+
+#[derive(Deserialize)]
+struct MembersChunk {
+    members: Vec<Member>,
+    chunk_count: u32,
+    #[serde(default)]
+    not_found: Vec<Snowflake>,
+    #[serde(default)]
+    presences: Vec<UserPresence>,
+}
+
+struct PendingRequest {
+    /// Whether the request has been written to the connection of the current session
+    sent: bool,
+    received_chunks: u32,
+    members: GuildMembers,
+    done: oneshot::Sender<Result<GuildMembers, Error>>,
+}
+
+/// Member requests waiting for GUILD_MEMBERS_CHUNK events
+#[derive(Default)]
+pub(crate) struct MemberRequests {
+    next_nonce: AtomicU64,
+    pending: Mutex<PendingRequests>,
+}
+
+#[derive(Default)]
+struct PendingRequests {
+    /// Incremented for each new gateway session
+    session: u64,
+    /// Requests keyed by nonce
+    requests: HashMap<String, PendingRequest>,
+}
+
+impl MemberRequests {
+    /// Register a new request, returning its nonce and a receiver for the result
+    pub fn start(&self) -> (String, oneshot::Receiver<Result<GuildMembers, Error>>) {
+        let nonce = self.next_nonce.fetch_add(1, Ordering::Relaxed).to_string();
+        let (done, done_rx) = oneshot::channel();
+        self.pending.lock().unwrap().requests.insert(
+            nonce.clone(),
+            PendingRequest {
+                sent: false,
+                received_chunks: 0,
+                members: Default::default(),
+                done,
+            },
+        );
+        (nonce, done_rx)
+    }
+
+    pub fn cancel(&self, nonce: &str) {
+        self.pending.lock().unwrap().requests.remove(nonce);
+    }
+
+    /// Drop all pending requests, since nothing will send them or their chunks anymore
+    pub fn clear(&self) {
+        self.pending.lock().unwrap().requests.clear();
+    }
+
+    /// Current session, to pass to [`mark_sent`](Self::mark_sent)
+    pub fn session(&self) -> u64 {
+        self.pending.lock().unwrap().session
+    }
+
+    /// Start a new session, dropping requests sent in earlier ones since their chunks will never
+    /// arrive. Requests that are still queued are kept.
+    pub fn start_session(&self) -> u64 {
+        let mut pending = self.pending.lock().unwrap();
+        pending.session += 1;
+        pending.requests.retain(|_, request| !request.sent);
+        pending.session
+    }
+
+    /// Record that a request was written to the connection of the given session
+    pub fn mark_sent(&self, nonce: &str, session: u64) {
+        let mut pending = self.pending.lock().unwrap();
+        if session == pending.session {
+            if let Some(request) = pending.requests.get_mut(nonce) {
+                request.sent = true;
+            }
+        } else {
+            // sent on a connection whose session has already ended
+            pending.requests.remove(nonce);
+        }
+    }
+
+    /// Add a GUILD_MEMBERS_CHUNK to its request, returning `false` if it doesn't belong to one
+    pub fn handle_chunk(&self, d: &serde_json::Value) -> bool {
+        let nonce = match d["nonce"].as_str() {
+            Some(nonce) => nonce,
+            None => return false,
+        };
+        let mut pending = self.pending.lock().unwrap();
+        let pending = &mut pending.requests;
+        let request = match pending.get_mut(nonce) {
+            Some(request) => request,
+            None => return false,
+        };
+
+        match serde_json::from_value::<MembersChunk>(d.clone()) {
+            Err(err) => {
+                // fail the request rather than returning partial results
+                if let Some(request) = pending.remove(nonce) {
+                    let _ = request.done.send(Err(Error::Serialization(err)));
+                }
+            }
+            Ok(chunk) => {
+                request.received_chunks += 1;
+                request.members.members.extend(chunk.members);
+                request.members.not_found.extend(chunk.not_found);
+                request.members.presences.extend(chunk.presences);
+                if request.received_chunks >= chunk.chunk_count {
+                    if let Some(request) = pending.remove(nonce) {
+                        let _ = request.done.send(Ok(request.members));
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oneshot::error::TryRecvError;
+    use serde_json::json;
+
+    fn member(id: &str) -> serde_json::Value {
+        json!({
+            "user": {"id": id, "username": "member", "discriminator": "0", "avatar": null},
+            "roles": [],
+        })
+    }
+
+    fn chunk(nonce: &str, chunk_count: u32, members: &[&str]) -> serde_json::Value {
+        json!({
+            "guild_id": "1",
+            "members": members.iter().map(|id| member(id)).collect::<Vec<_>>(),
+            "chunk_index": 0,
+            "chunk_count": chunk_count,
+            "nonce": nonce,
+        })
+    }
+
+    #[test]
+    fn reassembles_chunks() {
+        let requests = MemberRequests::default();
+        let (nonce, mut done) = requests.start();
+
+        assert!(requests.handle_chunk(&chunk(&nonce, 3, &["2", "3"])));
+        let mut with_extra = chunk(&nonce, 3, &["4"]);
+        with_extra["not_found"] = json!(["5"]);
+        with_extra["presences"] = json!([{"user": {"id": "4"}, "status": "idle"}]);
+        assert!(requests.handle_chunk(&with_extra));
+        assert!(matches!(done.try_recv(), Err(TryRecvError::Empty)));
+
+        let mut last = chunk(&nonce, 3, &[]);
+        last["not_found"] = json!(["6"]);
+        assert!(requests.handle_chunk(&last));
+        let members = done.try_recv().unwrap().unwrap();
+        let ids: Vec<_> = members.members.iter().map(|m| &m.user.id[..]).collect();
+        assert_eq!(ids, ["2", "3", "4"]);
+        assert_eq!(members.not_found, ["5", "6"]);
+        assert_eq!(members.presences.len(), 1);
+        assert_eq!(members.presences[0].user_id, "4");
+        assert_eq!(members.presences[0].status, crate::Status::Idle);
+
+        // the request is finished, so further chunks are not claimed
+        assert!(!requests.handle_chunk(&chunk(&nonce, 3, &["7"])));
+    }
+
+    #[test]
+    fn ignores_chunks_for_other_requests() {
+        let requests = MemberRequests::default();
+        let (nonce, mut done) = requests.start();
+
+        let mut without_nonce = chunk(&nonce, 1, &["2"]);
+        without_nonce.as_object_mut().unwrap().remove("nonce");
+        assert!(!requests.handle_chunk(&without_nonce));
+        assert!(!requests.handle_chunk(&chunk("unknown", 1, &["2"])));
+        assert!(matches!(done.try_recv(), Err(TryRecvError::Empty)));
+
+        assert!(requests.handle_chunk(&chunk(&nonce, 1, &["2"])));
+        assert_eq!(done.try_recv().unwrap().unwrap().members.len(), 1);
+    }
+
+    #[test]
+    fn bad_chunk_fails_request() {
+        let requests = MemberRequests::default();
+        let (nonce, mut done) = requests.start();
+        let (other, mut other_done) = requests.start();
+
+        assert!(requests.handle_chunk(&chunk(&nonce, 2, &["2"])));
+        assert!(requests.handle_chunk(&json!({"nonce": nonce, "chunk_count": 2})));
+        assert!(matches!(done.try_recv(), Ok(Err(Error::Serialization(_)))));
+        assert!(!requests.handle_chunk(&chunk(&nonce, 2, &["3"])));
+
+        assert!(matches!(other_done.try_recv(), Err(TryRecvError::Empty)));
+        assert!(requests.handle_chunk(&chunk(&other, 1, &[])));
+        assert!(matches!(other_done.try_recv(), Ok(Ok(_))));
+    }
+
+    #[test]
+    fn new_session_drops_only_sent_requests() {
+        let requests = MemberRequests::default();
+        let session = requests.start_session();
+        let (sent, mut sent_done) = requests.start();
+        let (queued, mut queued_done) = requests.start();
+        requests.mark_sent(&sent, session);
+
+        let next = requests.start_session();
+        assert!(matches!(sent_done.try_recv(), Err(TryRecvError::Closed)));
+        assert!(matches!(queued_done.try_recv(), Err(TryRecvError::Empty)));
+
+        // written by the previous connection after the new session started
+        let (late, mut late_done) = requests.start();
+        requests.mark_sent(&late, session);
+        assert!(matches!(late_done.try_recv(), Err(TryRecvError::Closed)));
+
+        requests.mark_sent(&queued, next);
+        assert_eq!(requests.session(), next);
+        assert!(requests.handle_chunk(&chunk(&queued, 1, &["2"])));
+        assert!(matches!(queued_done.try_recv(), Ok(Ok(_))));
+    }
+}
@@ -1,75 +1,256 @@
-pub use self::stream::GatewayConnection;
-use crate::types::Snowflake;
-use crate::Error;
-use serde_derive::Deserialize;
+pub use self::builder::ClientBuilder;
+pub use self::members::{GuildMembers, MemberQuery};
+pub use self::shard::ShardManager;
+pub use self::stream::{GatewayConnection, GatewayHandle};
+use crate::types::{GatewayBotInfo, SessionStartLimit, Snowflake};
+use crate::{Error, Intents, Presence};
+use std::sync::Arc;
+use std::time::Duration;
 
+mod builder;
+mod commands;
+mod compression;
+mod encoding;
+#[cfg(feature = "etf")]
+mod etf;
+mod identify;
+mod members;
+mod ratelimit;
+mod shard;
 mod stream;
 
+const API_BASE: &str = "https://discord.com/api/v10";
+
 async fn res_to_error(
     res: hyper::Response<hyper::Body>,
 ) -> Result<hyper::Response<hyper::Body>, crate::Error> {
-    if res.status().is_success() {
+    let status = res.status();
+    if status.is_success() {
         Ok(res)
+    } else if status == hyper::StatusCode::UNAUTHORIZED {
+        Err(Error::AuthenticationFailed)
     } else {
         let bytes = hyper::body::to_bytes(res.into_body()).await?;
-        Err(Error::Other(format!(
-            "Error in remote response: {}",
-            String::from_utf8_lossy(&bytes)
-        )))
+        Err(match serde_json::from_slice(&bytes) {
+            Ok(error) => Error::Api { status, error },
+            Err(_) => Error::Status {
+                status,
+                body: String::from_utf8_lossy(&bytes).into_owned(),
+            },
+        })
+    }
+}
+
+fn check_shard(shard_id: u64, shard_count: u64) -> Result<(), Error> {
+    if shard_id >= shard_count {
+        return Err(Error::Other(format!(
+            "Invalid shard {} of {}",
+            shard_id, shard_count
+        )));
     }
+    Ok(())
 }
 
 /// Object used to interact with the Discord API
 pub struct Client {
     http_client: hyper::Client<hyper_tls::HttpsConnector<hyper::client::HttpConnector>>,
     auth_value: String,
+    api_base: String,
+    user_agent: String,
+    timeout: Option<Duration>,
+    ratelimiter: ratelimit::RateLimiter,
+    gateway_config: GatewayConfig,
+    identify_limiter: Arc<identify::IdentifyLimiter>,
+}
+
+/// Settings for gateway connections opened by a [`Client`]
+struct GatewayConfig {
+    gateway_url: Option<String>,
+    tls: bool,
+    intents: Intents,
+    max_reconnect_delay: Duration,
+    shard: Option<[u64; 2]>,
+    presence: Option<Presence>,
+    compress: bool,
+    encoding: encoding::Encoding,
 }
 
 impl Client {
-    /// Connect to the Discord gateway with a bot token
+    /// Create a REST-only client for a bot token, without connecting to the gateway
+    pub fn new(token: &str) -> Client {
+        ClientBuilder::new(token).build()
+    }
+
+    /// Connect to the Discord gateway with a bot token, using [`Intents::non_privileged`]
     pub async fn connect(token: &str) -> Result<(Client, stream::GatewayConnection), Error> {
-        let auth_value = format!("Bot {}", token);
+        ClientBuilder::new(token).connect().await
+    }
 
-        let http = hyper::Client::builder().build(hyper_tls::HttpsConnector::new());
+    /// Connect to the Discord gateway with a bot token, receiving events for the given intents
+    pub async fn connect_with_intents(
+        token: &str,
+        intents: Intents,
+    ) -> Result<(Client, stream::GatewayConnection), Error> {
+        ClientBuilder::new(token)
+            .with_intents(intents)
+            .connect()
+            .await
+    }
+
+    fn build_request(&self, method: hyper::Method, url: &str) -> hyper::http::request::Builder {
+        hyper::Request::builder()
+            .method(method)
+            .uri(url)
+            .header(hyper::header::AUTHORIZATION, &self.auth_value)
+            .header(hyper::header::USER_AGENT, &self.user_agent)
+    }
 
-        let gateway_req = hyper::Request::get("https://discordapp.com/api/v6/gateway/bot")
-            .header(hyper::header::AUTHORIZATION, &auth_value)
-            .body(Default::default())
-            .map_err(|e| Error::Other(format!("{:?}", e)))?;
+    async fn request(
+        &self,
+        req: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, Error> {
+        let res = self.http_client.request(req);
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, res)
+                .await
+                .map_err(|_| Error::Timeout)?
+                .map_err(Into::into),
+            None => res.await.map_err(Into::into),
+        }
+    }
 
-        let resp = http.request(gateway_req).await?;
-        let body = match resp.status() {
-            hyper::StatusCode::UNAUTHORIZED => {
-                return Err(Error::AuthenticationFailed);
+    /// Make a request, waiting as needed to respect rate limits and retrying if limited anyway
+    async fn request_limited(
+        &self,
+        route: ratelimit::Route<'_>,
+        build: impl Fn() -> Result<hyper::Request<hyper::Body>, Error>,
+    ) -> Result<hyper::Response<hyper::Body>, Error> {
+        loop {
+            let bucket = self.ratelimiter.bucket(&route);
+            self.ratelimiter.wait_global().await;
+            let mut state = bucket.lock().await;
+            if let Some(delay) = state.delay() {
+                drop(state);
+                tokio::time::sleep(delay).await;
+                continue;
             }
-            hyper::StatusCode::OK => hyper::body::to_bytes(resp.into_body()).await?,
-            status => {
-                return Err(Error::Other(format!(
-                    "Gateway request returned unexpected status {}",
-                    status
-                )));
+            // once the limit is known, reserve a request and let others proceed concurrently,
+            // otherwise hold the bucket until this response tells us the limit
+            let held = if state.reserve() {
+                drop(state);
+                None
+            } else {
+                Some(state)
+            };
+
+            let res = self.request(build()?).await?;
+            let mut state = match held {
+                Some(state) => state,
+                None => bucket.lock().await,
+            };
+            match self.ratelimiter.update(&route, &mut state, &res) {
+                Some((retry_after, global)) if matches!(self.timeout, Some(timeout) if retry_after > timeout) =>
+                {
+                    return Err(Error::RateLimited {
+                        retry_after,
+                        global,
+                    });
+                }
+                Some((retry_after, _)) => {
+                    eprintln!(
+                        "Rate limited on {} {}, retrying in {:?}",
+                        route.method, route.path, retry_after
+                    );
+                }
+                None => return Ok(res),
             }
+        }
+    }
+
+    /// Get the gateway URL and recommended shard count
+    pub async fn get_gateway_bot(&self) -> Result<GatewayBotInfo, Error> {
+        let url = format!("{}/gateway/bot", self.api_base);
+        let route = ratelimit::Route {
+            method: hyper::Method::GET,
+            path: "/gateway/bot",
+            major: "",
         };
+        let resp = self
+            .request_limited(route, || {
+                self.build_request(hyper::Method::GET, &url)
+                    .body(Default::default())
+                    .map_err(Error::Request)
+            })
+            .await?;
+        let resp = res_to_error(resp).await?;
+        let body = hyper::body::to_bytes(resp.into_body()).await?;
+
+        let info: GatewayBotInfo = serde_json::from_slice(&body).map_err(Error::Serialization)?;
+        self.identify_limiter.update(&info.session_start_limit);
+        Ok(info)
+    }
 
-        #[derive(Deserialize)]
-        struct GetGatewayResult<'a> {
-            url: &'a str,
+    /// Last known limit on starting gateway sessions, including identifies sent since.
+    ///
+    /// Available once `/gateway/bot` has been requested, e.g. when connecting to the gateway.
+    pub fn session_start_limit(&self) -> Option<SessionStartLimit> {
+        self.identify_limiter.current()
+    }
+
+    /// Connect to the gateway, as the shard set with [`ClientBuilder::set_shard`] if any
+    pub async fn connect_gateway(&self) -> Result<GatewayConnection, Error> {
+        if let Some([shard_id, shard_count]) = self.gateway_config.shard {
+            check_shard(shard_id, shard_count)?;
         }
-        let result: GetGatewayResult = serde_json::from_slice(&body)
-            .map_err(|e| Error::Other(format!("Unable to parse Gateway API response: {:?}", e)))?;
-
-        println!("{}", result.url);
-        url::Url::parse(&result.url)
-            .map_err(|e| Error::Other(format!("Unable to parse Gateway URL: {:?}", e)))
-            .map(|url| {
-                (
-                    Client {
-                        http_client: http,
-                        auth_value: auth_value.clone(),
-                    },
-                    stream::GatewayConnection::connect_new(url, auth_value),
-                )
-            })
+        let url = self.get_gateway_url().await?;
+        Ok(self.gateway_connection(url, self.gateway_config.shard))
+    }
+
+    /// Connect to the gateway as a specific shard
+    pub async fn connect_shard(
+        &self,
+        shard_id: u64,
+        shard_count: u64,
+    ) -> Result<GatewayConnection, Error> {
+        check_shard(shard_id, shard_count)?;
+        let url = self.get_gateway_url().await?;
+        Ok(self.gateway_connection(url, Some([shard_id, shard_count])))
+    }
+
+    /// Get the URL to connect to the gateway, including version parameters
+    async fn get_gateway_url(&self) -> Result<url::Url, Error> {
+        let url = match self.gateway_config.gateway_url {
+            Some(ref url) => url.clone(),
+            None => self.get_gateway_bot().await?.url,
+        };
+        self.parse_gateway_url(&url)
+    }
+
+    fn parse_gateway_url(&self, url: &str) -> Result<url::Url, Error> {
+        let url = url::Url::parse(url)
+            .map_err(|e| Error::Other(format!("Unable to parse Gateway URL: {:?}", e)))?;
+        if self.gateway_config.tls && url.scheme() != "wss" {
+            return Err(Error::Other(format!(
+                "Gateway URL {} does not use TLS",
+                url
+            )));
+        }
+
+        Ok(stream::with_gateway_params(url))
+    }
+
+    fn gateway_connection(&self, url: url::Url, shard: Option<[u64; 2]>) -> GatewayConnection {
+        GatewayConnection::connect_new(stream::ConnParams {
+            token: self.auth_value.clone(),
+            url,
+            intents: self.gateway_config.intents,
+            max_reconnect_delay: self.gateway_config.max_reconnect_delay,
+            shard,
+            presence: self.gateway_config.presence.clone(),
+            compress: self.gateway_config.compress,
+            encoding: self.gateway_config.encoding,
+            identify_limiter: self.identify_limiter.clone(),
+        })
     }
 
     /// Send a message on a channel
@@ -79,17 +260,27 @@ impl Client {
         channel: &str,
     ) -> Result<(), Error> {
         let body = message.to_request_body(channel)?;
-        let req = hyper::Request::post(format!(
-            "https://discordapp.com/api/v6/channels/{}/messages",
-            channel
-        ))
-        .header(hyper::header::AUTHORIZATION, &self.auth_value)
-        .header(hyper::header::CONTENT_TYPE, "application/json")
-        .header(hyper::header::CONTENT_LENGTH, body.len())
-        .body(body.into())
-        .map_err(|e| Error::Other(format!("Failed to create request: {:?}", e)))?;
-
-        res_to_error(self.http_client.request(req).await?).await?;
+        let url = format!(
+            "{}/channels/{}/messages",
+            self.api_base,
+            percent_encoding::utf8_percent_encode(channel, percent_encoding::NON_ALPHANUMERIC),
+        );
+        let route = ratelimit::Route {
+            method: hyper::Method::POST,
+            path: "/channels/{channel_id}/messages",
+            major: channel,
+        };
+
+        let res = self
+            .request_limited(route, || {
+                self.build_request(hyper::Method::POST, &url)
+                    .header(hyper::header::CONTENT_TYPE, "application/json")
+                    .header(hyper::header::CONTENT_LENGTH, body.len())
+                    .body(body.clone().into())
+                    .map_err(Error::Request)
+            })
+            .await?;
+        res_to_error(res).await?;
 
         Ok(())
     }
@@ -102,7 +293,8 @@ impl Client {
         limit: impl Into<Option<u8>>,
     ) -> Result<Vec<crate::types::Message>, Error> {
         let mut url = format!(
-            "https://discordapp.com/api/v6/channels/{}/messages?",
+            "{}/channels/{}/messages?",
+            self.api_base,
             percent_encoding::utf8_percent_encode(channel, percent_encoding::NON_ALPHANUMERIC),
         );
 
@@ -116,16 +308,23 @@ impl Client {
             }
         }
 
-        let req = hyper::Request::get(url)
-            .header(hyper::header::AUTHORIZATION, &self.auth_value)
-            .body(Default::default())
-            .map_err(|e| Error::Other(format!("Failed to create request: {:?}", e)))?;
+        let route = ratelimit::Route {
+            method: hyper::Method::GET,
+            path: "/channels/{channel_id}/messages",
+            major: channel,
+        };
 
-        let res = res_to_error(self.http_client.request(req).await?).await?;
+        let res = self
+            .request_limited(route, || {
+                self.build_request(hyper::Method::GET, &url)
+                    .body(Default::default())
+                    .map_err(Error::Request)
+            })
+            .await?;
+        let res = res_to_error(res).await?;
         let body = hyper::body::to_bytes(res.into_body()).await?;
 
-        serde_json::from_slice(&body)
-            .map_err(|e| Error::Other(format!("Unable to parse Gateway API response: {:?}", e)))
+        serde_json::from_slice(&body).map_err(Error::Serialization)
     }
 }
 
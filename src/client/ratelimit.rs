@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A REST route, used to choose the rate limit bucket for a request
+pub(crate) struct Route<'a> {
+    pub method: hyper::Method,
+    /// Path with parameters left as placeholders, e.g. `/channels/{channel_id}/messages`
+    pub path: &'static str,
+    /// Value of the major parameter (channel, guild, or webhook ID), or empty if there is none
+    pub major: &'a str,
+}
+
+type Bucket = Arc<tokio::sync::Mutex<BucketState>>;
+
+/// Tracks Discord's rate limits and delays requests to stay within them
+#[derive(Default)]
+pub(crate) struct RateLimiter {
+    /// Bucket hashes reported by Discord for each route
+    bucket_hashes: Mutex<HashMap<(hyper::Method, &'static str), String>>,
+    /// Buckets keyed by hash (or path, if not known yet) and major parameter
+    buckets: Mutex<HashMap<(String, String), Bucket>>,
+    /// When the global rate limit expires, if it has been hit
+    global_reset: Mutex<Option<Instant>>,
+}
+
+#[derive(Default)]
+pub(crate) struct BucketState {
+    remaining: Option<u64>,
+    reset: Option<Instant>,
+}
+
+impl BucketState {
+    /// How long to wait before a request can be made in this bucket, if it is exhausted
+    pub fn delay(&mut self) -> Option<Duration> {
+        if let (Some(0), Some(reset)) = (self.remaining, self.reset) {
+            let now = Instant::now();
+            if reset > now {
+                return Some(reset - now);
+            }
+            self.remaining = None;
+            self.reset = None;
+        }
+        None
+    }
+
+    /// Take one of the remaining requests, returning `false` if the limit is not known yet
+    pub fn reserve(&mut self) -> bool {
+        match self.remaining {
+            Some(ref mut remaining) => {
+                *remaining = remaining.saturating_sub(1);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn parse_header<T: FromStr>(headers: &hyper::HeaderMap, name: &str) -> Option<T> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+fn parse_seconds(headers: &hyper::HeaderMap, name: &str) -> Option<Duration> {
+    parse_header::<f64>(headers, name)
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+impl RateLimiter {
+    /// Get the bucket for a route
+    pub fn bucket(&self, route: &Route) -> Bucket {
+        let key = match self
+            .bucket_hashes
+            .lock()
+            .unwrap()
+            .get(&(route.method.clone(), route.path))
+        {
+            Some(hash) => hash.clone(),
+            None => route.path.to_owned(),
+        };
+
+        self.buckets
+            .lock()
+            .unwrap()
+            .entry((key, route.major.to_owned()))
+            .or_default()
+            .clone()
+    }
+
+    /// Wait until the global rate limit has expired
+    pub async fn wait_global(&self) {
+        let reset = *self.global_reset.lock().unwrap();
+        if let Some(reset) = reset {
+            tokio::time::sleep(reset.saturating_duration_since(Instant::now())).await;
+        }
+    }
+
+    /// Record the rate limit headers of a response.
+    ///
+    /// If the request was rate limited, returns how long to wait before retrying
+    /// and whether the limit was global.
+    pub fn update(
+        &self,
+        route: &Route,
+        bucket: &mut BucketState,
+        res: &hyper::Response<hyper::Body>,
+    ) -> Option<(Duration, bool)> {
+        let headers = res.headers();
+        let now = Instant::now();
+
+        if let Some(hash) = parse_header::<String>(headers, "x-ratelimit-bucket") {
+            self.bucket_hashes
+                .lock()
+                .unwrap()
+                .insert((route.method.clone(), route.path), hash);
+        }
+
+        let reset_after = parse_seconds(headers, "x-ratelimit-reset-after");
+        if let Some(remaining) = parse_header(headers, "x-ratelimit-remaining") {
+            bucket.remaining = Some(remaining);
+            bucket.reset = reset_after.map(|after| now + after);
+        }
+
+        if res.status() != hyper::StatusCode::TOO_MANY_REQUESTS {
+            return None;
+        }
+
+        let retry_after = parse_seconds(headers, "retry-after")
+            .or(reset_after)
+            .unwrap_or_else(|| Duration::from_secs(1));
+        let global = parse_header(headers, "x-ratelimit-global").unwrap_or(false);
+
+        if global {
+            *self.global_reset.lock().unwrap() = Some(now + retry_after);
+        } else {
+            bucket.remaining = Some(0);
+            bucket.reset = Some(now + retry_after);
+        }
+
+        Some((retry_after, global))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(path: &'static str) -> Route<'static> {
+        Route {
+            method: hyper::Method::GET,
+            path,
+            major: "1",
+        }
+    }
+
+    fn response(status: u16, headers: &[(&str, &str)]) -> hyper::Response<hyper::Body> {
+        let mut builder = hyper::Response::builder().status(status);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(hyper::Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn remaps_routes_to_bucket_hash() {
+        let limiter = RateLimiter::default();
+        let first = route("/first/{id}");
+        let second = route("/second/{id}");
+        let before = limiter.bucket(&first);
+        assert!(!Arc::ptr_eq(&before, &limiter.bucket(&second)));
+
+        let res = response(200, &[("x-ratelimit-bucket", "abc")]);
+        limiter.update(&first, &mut BucketState::default(), &res);
+        limiter.update(&second, &mut BucketState::default(), &res);
+
+        let after = limiter.bucket(&first);
+        assert!(!Arc::ptr_eq(&before, &after));
+        assert!(Arc::ptr_eq(&after, &limiter.bucket(&second)));
+        assert!(!Arc::ptr_eq(
+            &after,
+            &limiter.bucket(&Route {
+                major: "2",
+                ..route("/first/{id}")
+            })
+        ));
+    }
+
+    #[test]
+    fn parses_reset_after() {
+        let limiter = RateLimiter::default();
+        let mut bucket = BucketState::default();
+        let res = response(
+            200,
+            &[
+                ("x-ratelimit-remaining", "0"),
+                ("x-ratelimit-reset-after", "1.5"),
+            ],
+        );
+        assert_eq!(limiter.update(&route("/a"), &mut bucket, &res), None);
+        assert_eq!(bucket.remaining, Some(0));
+        let delay = bucket.delay().unwrap();
+        assert!(delay > Duration::from_millis(1400) && delay <= Duration::from_millis(1500));
+
+        let res = response(
+            200,
+            &[
+                ("x-ratelimit-remaining", "4"),
+                ("x-ratelimit-reset-after", "-1"),
+            ],
+        );
+        limiter.update(&route("/a"), &mut bucket, &res);
+        assert_eq!(bucket.remaining, Some(4));
+        assert_eq!(bucket.reset, None);
+        assert!(bucket.reserve());
+        assert_eq!(bucket.remaining, Some(3));
+    }
+
+    #[test]
+    fn handles_route_rate_limit() {
+        let limiter = RateLimiter::default();
+        let mut bucket = BucketState::default();
+        let res = response(429, &[("retry-after", "2")]);
+        assert_eq!(
+            limiter.update(&route("/a"), &mut bucket, &res),
+            Some((Duration::from_secs(2), false))
+        );
+        assert_eq!(bucket.remaining, Some(0));
+        assert!(bucket.delay().unwrap() > Duration::from_secs(1));
+        assert!(limiter.global_reset.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn handles_global_rate_limit() {
+        let limiter = RateLimiter::default();
+        let mut bucket = BucketState::default();
+        let res = response(429, &[("retry-after", "3"), ("x-ratelimit-global", "true")]);
+        assert_eq!(
+            limiter.update(&route("/a"), &mut bucket, &res),
+            Some((Duration::from_secs(3), true))
+        );
+        assert_eq!(bucket.remaining, None);
+        assert!(limiter.global_reset.lock().unwrap().unwrap() > Instant::now());
+    }
+}
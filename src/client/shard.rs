@@ -0,0 +1,120 @@
+use super::Client;
+use crate::{Error, Event, GatewayConnection, GatewayHandle};
+use futures::Stream;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Runs every shard of a bot in one process, merging their events into a single stream
+///
+/// Each item is tagged with the ID of the shard it came from.
+/// Shards that close fatally are stopped until [`restart`](ShardManager::restart)ed.
+/// The stream ends once no shard is running, and yields events again if polled after a restart.
+pub struct ShardManager {
+    client: Arc<Client>,
+    url: url::Url,
+    shards: Vec<Option<GatewayConnection>>,
+    /// Shard to poll first, rotated for fairness
+    next_poll: usize,
+}
+
+impl ShardManager {
+    /// Connect the number of shards recommended by Discord
+    pub async fn connect(client: Arc<Client>) -> Result<Self, Error> {
+        let info = client.get_gateway_bot().await?;
+        let url = client.parse_gateway_url(
+            client
+                .gateway_config
+                .gateway_url
+                .as_deref()
+                .unwrap_or(&info.url),
+        )?;
+        Ok(Self::connect_internal(client, url, info.shards))
+    }
+
+    /// Connect a specific number of shards
+    pub async fn connect_with_count(client: Arc<Client>, shard_count: u64) -> Result<Self, Error> {
+        let url = client.get_gateway_url().await?;
+        Ok(Self::connect_internal(client, url, shard_count))
+    }
+
+    fn connect_internal(client: Arc<Client>, url: url::Url, shard_count: u64) -> Self {
+        let shards = (0..shard_count)
+            .map(|shard_id| {
+                Some(client.gateway_connection(url.clone(), Some([shard_id, shard_count])))
+            })
+            .collect();
+        Self {
+            client,
+            url,
+            shards,
+            next_poll: 0,
+        }
+    }
+
+    /// Number of shards being run
+    pub fn shard_count(&self) -> u64 {
+        self.shards.len() as u64
+    }
+
+    /// Get a handle to a shard's connection, if it is still running.
+    ///
+    /// Handles are invalidated when the shard is restarted.
+    pub fn shard_handle(&self, shard_id: u64) -> Option<GatewayHandle> {
+        self.shards
+            .get(shard_id as usize)
+            .and_then(Option::as_ref)
+            .map(GatewayConnection::handle)
+    }
+
+    /// Replace a shard's connection with a fresh one, leaving the other shards untouched
+    pub fn restart(&mut self, shard_id: u64) -> Result<(), Error> {
+        let shard_count = self.shard_count();
+        let connection = self
+            .client
+            .gateway_connection(self.url.clone(), Some([shard_id, shard_count]));
+        match self.shards.get_mut(shard_id as usize) {
+            Some(slot) => {
+                *slot = Some(connection);
+                Ok(())
+            }
+            None => Err(Error::Other(format!(
+                "Invalid shard {} of {}",
+                shard_id, shard_count
+            ))),
+        }
+    }
+}
+
+impl Stream for ShardManager {
+    type Item = (u64, Result<Event, Error>);
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        ctx: &mut futures::task::Context,
+    ) -> futures::task::Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let count = this.shards.len();
+        let mut any_running = false;
+        for i in 0..count {
+            let idx = (this.next_poll + i) % count;
+            if let Some(ref mut shard) = this.shards[idx] {
+                match Pin::new(shard).poll_next(ctx) {
+                    futures::task::Poll::Ready(Some(item)) => {
+                        this.next_poll = (idx + 1) % count;
+                        return futures::task::Poll::Ready(Some((idx as u64, item)));
+                    }
+                    futures::task::Poll::Ready(None) => {
+                        // shard closed fatally, leave it stopped until restarted
+                        this.shards[idx] = None;
+                    }
+                    futures::task::Poll::Pending => any_running = true,
+                }
+            }
+        }
+        if any_running {
+            futures::task::Poll::Pending
+        } else {
+            futures::task::Poll::Ready(None)
+        }
+    }
+}
@@ -1,48 +1,261 @@
-use crate::{Error, Event};
+use super::commands::{Command, SendLimiter};
+use super::compression::Inflater;
+use super::encoding::Encoding;
+use super::members::{GuildMembers, MemberQuery, MemberRequests};
+use crate::{CloseCode, Error, Event, Intents, Presence};
 
-use futures::{SinkExt, Stream, StreamExt, TryStreamExt};
+use futures::{FutureExt, SinkExt, Stream, StreamExt, TryStreamExt};
+use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio_tungstenite::tungstenite;
 
-struct ConnParams {
-    token: String,
-    url: url::Url,
+/// Configuration for a gateway connection
+pub(crate) struct ConnParams {
+    pub token: String,
+    pub url: url::Url,
+    pub intents: Intents,
+    /// Upper bound for the delay between reconnect attempts
+    pub max_reconnect_delay: Duration,
+    /// `[shard_id, shard_count]`, if sharding
+    pub shard: Option<[u64; 2]>,
+    /// Presence to identify with, until one is set through a [`GatewayHandle`]
+    pub presence: Option<Presence>,
+    /// Whether to use zlib-stream transport compression
+    pub compress: bool,
+    pub encoding: Encoding,
+    pub identify_limiter: Arc<super::identify::IdentifyLimiter>,
 }
 
+/// Gateway protocol version
+const GATEWAY_VERSION: &str = "10";
+
+/// Delay before the second consecutive reconnect attempt, doubled for each one after that
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Number of heartbeats used for the rolling latency average
+const LATENCY_SAMPLES: usize = 10;
+
 /// Stream of gateway events
+///
+/// Reconnects automatically, ending only after yielding [`Error::GatewayClosed`] for a fatal close.
 pub struct GatewayConnection {
     conn_params: Arc<ConnParams>,
+    shared: Arc<Shared>,
     state: ConnectionState,
 }
 
+/// Cloneable handle to a [`GatewayConnection`], usable while its events are being consumed
+#[derive(Clone)]
+pub struct GatewayHandle {
+    shared: Arc<Shared>,
+}
+
+/// State shared between the connection, its tasks, and any handles
+struct Shared {
+    session: Mutex<SessionState>,
+    latency: Mutex<LatencyStats>,
+    /// Reconnect attempts since the last successful READY or RESUMED
+    reconnect_attempts: AtomicU32,
+    /// Presence sent when identifying
+    presence: Mutex<Option<Presence>>,
+    member_requests: MemberRequests,
+    /// Guilds that have not been loaded yet or are in an outage
+    unavailable_guilds: Mutex<HashSet<crate::Snowflake>>,
+    command_tx: tokio::sync::mpsc::UnboundedSender<Command>,
+    /// Queued commands, held by the writer task of the current connection
+    command_rx: tokio::sync::Mutex<tokio::sync::mpsc::UnboundedReceiver<Command>>,
+    /// Set once the connection has closed for good or been dropped
+    closed_tx: tokio::sync::watch::Sender<bool>,
+    closed_rx: tokio::sync::watch::Receiver<bool>,
+}
+
+impl Shared {
+    fn new(presence: Option<Presence>) -> Self {
+        let (command_tx, command_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (closed_tx, closed_rx) = tokio::sync::watch::channel(false);
+        Self {
+            session: Default::default(),
+            latency: Default::default(),
+            reconnect_attempts: AtomicU32::new(0),
+            presence: Mutex::new(presence),
+            member_requests: Default::default(),
+            unavailable_guilds: Default::default(),
+            command_tx,
+            command_rx: tokio::sync::Mutex::new(command_rx),
+            closed_tx,
+            closed_rx,
+        }
+    }
+
+    /// Fail queued and future commands and member requests, since nothing will send them anymore
+    fn close(&self) {
+        let _ = self.closed_tx.send(true);
+        // if a writer task still holds the queue, waiting commands are failed through `closed_rx`
+        if let Ok(mut commands) = self.command_rx.try_lock() {
+            commands.close();
+            while let Some(Some(_)) = commands.recv().now_or_never() {}
+        }
+        self.member_requests.clear();
+    }
+}
+
 enum ConnectionState {
     Pending(Pin<Box<dyn Future<Output = Result<ConnectionState, Error>> + Send>>),
-    Connected(Pin<Box<dyn Stream<Item = Result<Event, Error>> + Send>>),
+    Connected(Connection),
+    /// The gateway closed the connection for a reason that reconnecting won't fix
+    Closed,
+}
+
+struct Connection {
+    stream: Pin<Box<dyn Stream<Item = Result<Incoming, Error>> + Send>>,
+    heartbeat_tx: tokio::sync::mpsc::UnboundedSender<()>,
+    zombie_rx: tokio::sync::oneshot::Receiver<()>,
+    heartbeat_task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.heartbeat_task.abort();
+    }
+}
+
+/// Heartbeat bookkeeping for a single connection
+struct HeartbeatState {
+    acked: bool,
+    last_sent: Option<Instant>,
+}
+
+/// Recent heartbeat round-trip times, oldest first
+#[derive(Default)]
+struct LatencyStats {
+    samples: VecDeque<Duration>,
+}
+
+impl LatencyStats {
+    fn record(&mut self, sample: Duration) {
+        if self.samples.len() >= LATENCY_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn latest(&self) -> Option<Duration> {
+        self.samples.back().copied()
+    }
+
+    fn average(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            None
+        } else {
+            Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+        }
+    }
+}
+
+/// Session data kept across connections, used to resume after a disconnect
+#[derive(Default)]
+struct SessionState {
+    session_id: Option<String>,
+    seq: Option<u64>,
+    resume_url: Option<url::Url>,
 }
 
 struct ReconnectInfo {
     session_id: String,
     last_event: u64,
+    url: Option<url::Url>,
+}
+
+impl SessionState {
+    fn reconnect_info(&self) -> Option<ReconnectInfo> {
+        match (&self.session_id, self.seq) {
+            (Some(session_id), Some(last_event)) => Some(ReconnectInfo {
+                session_id: session_id.clone(),
+                last_event,
+                url: self.resume_url.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Result of handling a single gateway packet
+#[allow(clippy::large_enum_variant)]
+enum Incoming {
+    Event(Event),
+    /// The gateway asked for a heartbeat to be sent immediately
+    HeartbeatRequest,
+    /// The connection should be replaced after the given delay
+    Reconnect(Duration),
+    /// The connection was closed and should not be reestablished
+    Fatal(Error),
 }
 
 impl GatewayConnection {
-    pub(crate) fn connect_new(url: url::Url, token: String) -> Self {
-        let conn_params = Arc::new(ConnParams { token, url });
+    pub(crate) fn connect_new(conn_params: ConnParams) -> Self {
+        let conn_params = Arc::new(conn_params);
+        let shared = Arc::new(Shared::new(conn_params.presence.clone()));
         Self {
             state: ConnectionState::Pending(Box::pin(GatewayConnection::connect(
                 conn_params.clone(),
-                None,
+                shared.clone(),
             ))),
             conn_params,
+            shared,
+        }
+    }
+
+    /// Get a handle to this connection
+    pub fn handle(&self) -> GatewayHandle {
+        GatewayHandle {
+            shared: self.shared.clone(),
         }
     }
 
+    /// Round-trip time between the most recent heartbeat and its acknowledgement
+    pub fn latency(&self) -> Option<Duration> {
+        self.shared.latency.lock().unwrap().latest()
+    }
+
+    /// Average heartbeat round-trip time over the last few heartbeats
+    pub fn average_latency(&self) -> Option<Duration> {
+        self.shared.latency.lock().unwrap().average()
+    }
+
+    /// Number of reconnect attempts since the connection was last established
+    pub fn reconnect_attempts(&self) -> u32 {
+        self.shared.reconnect_attempts.load(Ordering::Relaxed)
+    }
+
+    /// `[shard_id, shard_count]` this connection identifies as, if sharding
+    pub fn shard(&self) -> Option<[u64; 2]> {
+        self.conn_params.shard
+    }
+
+    /// Start a new connection after the given delay, or longer if previous attempts have failed
+    fn reconnect(&self, delay: Duration) -> ConnectionState {
+        let attempt = self
+            .shared
+            .reconnect_attempts
+            .fetch_add(1, Ordering::Relaxed);
+        let delay = delay.max(backoff_delay(attempt, self.conn_params.max_reconnect_delay));
+        let conn_params = self.conn_params.clone();
+        let shared = self.shared.clone();
+        ConnectionState::Pending(Box::pin(async move {
+            tokio::time::sleep(delay).await;
+            GatewayConnection::connect(conn_params, shared).await
+        }))
+    }
+
     async fn connect(
         conn_params: Arc<ConnParams>,
-        resume_info: Option<ReconnectInfo>,
+        shared: Arc<Shared>,
     ) -> Result<ConnectionState, Error> {
         #[derive(Deserialize)]
         struct Hello {
@@ -54,6 +267,11 @@ impl GatewayConnection {
             token: &'a str,
             properties: IdentifyProperties<'a>,
             compress: bool,
+            intents: Intents,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            shard: Option<[u64; 2]>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            presence: Option<Presence>,
         }
 
         #[derive(Serialize)]
@@ -65,24 +283,63 @@ impl GatewayConnection {
 
         #[derive(Serialize)]
         struct IdentifyProperties<'a> {
-            #[serde(rename = "$os")]
             os: &'a str,
-            #[serde(rename = "$browser")]
             browser: &'a str,
-            #[serde(rename = "$device")]
             device: &'a str,
         }
 
-        let (socket, _) = tokio_tungstenite::connect_async(&conn_params.url).await?;
+        let (resume_info, member_session) = {
+            let mut session = shared.session.lock().unwrap();
+            let info = session.reconnect_info();
+            let member_session = if info.is_none() {
+                // starting a new session, so old sequence numbers are meaningless
+                *session = Default::default();
+                shared.member_requests.start_session()
+            } else {
+                shared.member_requests.session()
+            };
+            (info, member_session)
+        };
+
+        let mut url = match resume_info {
+            Some(ReconnectInfo {
+                url: Some(ref url), ..
+            }) => url.clone(),
+            _ => conn_params.url.clone(),
+        };
+        let encoding = conn_params.encoding;
+        url.query_pairs_mut()
+            .append_pair("encoding", encoding.name());
+        let mut inflater = if conn_params.compress {
+            url.query_pairs_mut().append_pair("compress", "zlib-stream");
+            Some(Inflater::new())
+        } else {
+            None
+        };
+
+        let identifying = resume_info.is_none();
+        if identifying {
+            let shard_id = conn_params.shard.map_or(0, |[shard_id, _]| shard_id);
+            conn_params.identify_limiter.wait(shard_id).await;
+        }
+
+        let (socket, _) = tokio_tungstenite::connect_async(url).await?;
         let (msg1, mut socket) = socket.into_future().await;
 
-        let msg1 = msg1.transpose()?;
+        let msg1 = match msg1.transpose()? {
+            Some(msg1) => inflate(&mut inflater, msg1)?,
+            None => None,
+        };
 
-        let (socket, hello) = if let Some(tungstenite::Message::Text(text)) = msg1 {
-            let payload: crate::DiscordBasePayload<Hello> = serde_json::from_str(&text)
-                .map_err(|e| Error::Other(format!("Failed to parse hello message: {:?}", e)))?;
+        let hello = match msg1 {
+            Some(tungstenite::Message::Text(ref text)) => Some(text.as_bytes()),
+            Some(tungstenite::Message::Binary(ref data)) => Some(&data[..]),
+            _ => None,
+        };
+        let (socket, hello) = if let Some(hello) = hello {
+            let payload: crate::DiscordBasePayload<Hello> = encoding.decode(hello)?;
             let first_packet = match resume_info {
-                Some(info) => serde_json::to_string(&crate::DiscordBasePayload {
+                Some(info) => encoding.encode(&crate::DiscordBasePayload {
                     op: 6,
                     d: Resume {
                         token: &conn_params.token,
@@ -90,28 +347,26 @@ impl GatewayConnection {
                         seq: info.last_event,
                     },
                 }),
-                None => {
-                    serde_json::to_string(&crate::DiscordBasePayload {
-                        op: 2,
-                        d: Identify {
-                            token: &conn_params.token,
-                            properties: IdentifyProperties {
-                                os: "linux", // TODO make this work
-                                browser: "noob",
-                                device: "noob",
-                            },
-                            compress: false,
+                None => encoding.encode(&crate::DiscordBasePayload {
+                    op: 2,
+                    d: Identify {
+                        token: &conn_params.token,
+                        properties: IdentifyProperties {
+                            os: std::env::consts::OS,
+                            browser: "noob",
+                            device: "noob",
                         },
-                    })
-                }
+                        compress: false,
+                        intents: conn_params.intents,
+                        shard: conn_params.shard,
+                        presence: shared.presence.lock().unwrap().clone(),
+                    },
+                }),
             };
-            let first_packet = first_packet.map_err(|e| {
-                Error::Other(format!("Failed to serialize identify message: {:?}", e))
-            })?;
-
-            socket
-                .send(tungstenite::Message::Text(first_packet))
-                .await?;
+            socket.send(first_packet?).await?;
+            if identifying {
+                conn_params.identify_limiter.record();
+            }
 
             Ok((socket, payload.d))
         } else {
@@ -120,38 +375,236 @@ impl GatewayConnection {
                 msg1
             )))
         }?;
-        let session_info: Arc<Mutex<Option<ReconnectInfo>>> = Arc::new(Mutex::new(None));
         let (mut sink, stream) = socket.split();
-        let mut heartbeat_interval =
-            tokio::time::interval(std::time::Duration::from_millis(hello.heartbeat_interval));
-        let session_info_hb = session_info.clone();
-        tokio::spawn(async move {
+        let heartbeat_interval = Duration::from_millis(hello.heartbeat_interval);
+        let mut send_limiter = SendLimiter::new(heartbeat_interval);
+        // the identify or resume
+        send_limiter.record();
+        let mut heartbeat_interval = tokio::time::interval(heartbeat_interval);
+        let (heartbeat_tx, mut heartbeat_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (zombie_tx, zombie_rx) = tokio::sync::oneshot::channel();
+        let heartbeat = Arc::new(Mutex::new(HeartbeatState {
+            acked: true,
+            last_sent: None,
+        }));
+        let heartbeat_hb = heartbeat.clone();
+        let shared_hb = shared.clone();
+        let heartbeat_task = tokio::spawn(async move {
+            enum Outgoing {
+                Heartbeat,
+                Command(Command),
+            }
+
+            let mut commands = shared_hb.command_rx.lock().await;
             loop {
-                heartbeat_interval.tick().await;
-                if let Err(err) = sink
-                    .send(tungstenite::Message::Text(
-                        serde_json::json!({
-                            "op": 1,
-                            "d": match *session_info_hb.lock().unwrap() {
-                                Some(ref info) => {
-                                    Some(info.last_event)
-                                },
-                                None => None
-                            }
-                        })
-                        .to_string(),
-                    ))
-                    .await
-                {
-                    eprintln!("Websocket error in heartbeat stream: {:?}", err);
+                let next_command_slot =
+                    tokio::time::Instant::from_std(send_limiter.next_command_slot());
+                let outgoing = tokio::select! {
+                    _ = heartbeat_interval.tick() => {
+                        let mut heartbeat = heartbeat_hb.lock().unwrap();
+                        if !heartbeat.acked {
+                            // previous heartbeat was never acknowledged, connection is dead
+                            let _ = zombie_tx.send(());
+                            break;
+                        }
+                        heartbeat.acked = false;
+                        Outgoing::Heartbeat
+                    }
+                    req = heartbeat_rx.recv() => {
+                        if req.is_none() {
+                            // connection was dropped
+                            break;
+                        }
+                        Outgoing::Heartbeat
+                    }
+                    command = async {
+                        tokio::time::sleep_until(next_command_slot).await;
+                        commands.recv().await
+                    } => match command {
+                        Some(command) => Outgoing::Command(command),
+                        None => break,
+                    },
+                };
+
+                send_limiter.record();
+                match outgoing {
+                    Outgoing::Heartbeat => {
+                        let payload = encoding.encode(&crate::DiscordBasePayload {
+                            op: 1,
+                            d: shared_hb.session.lock().unwrap().seq,
+                        });
+                        // with a heartbeat already in flight, the next ACK is most likely for that one
+                        heartbeat_hb
+                            .lock()
+                            .unwrap()
+                            .last_sent
+                            .get_or_insert_with(Instant::now);
+                        let res = match payload {
+                            Ok(payload) => sink.send(payload).await.map_err(Into::into),
+                            Err(err) => Err(err),
+                        };
+                        if let Err(err) = res {
+                            eprintln!("Error in heartbeat stream: {:?}", err);
+                        }
+                    }
+                    Outgoing::Command(command) => {
+                        let res = match encoding.encode(&command.payload) {
+                            Ok(payload) => sink.send(payload).await.map_err(Into::into),
+                            Err(err) => Err(err),
+                        };
+                        if let (Ok(()), Some(nonce)) = (&res, &command.member_request) {
+                            shared_hb.member_requests.mark_sent(nonce, member_session);
+                        }
+                        let _ = command.sent.send(res);
+                    }
                 }
             }
         });
-        Ok(ConnectionState::Connected(Box::pin(
-            stream.map_err(|e| e.into()).try_filter_map(move |packet| {
-                futures::future::ready(Ok(handle_packet(&session_info, packet)))
-            }),
-        )))
+        Ok(ConnectionState::Connected(Connection {
+            stream: Box::pin(stream.map_err(|e| e.into()).try_filter_map(move |packet| {
+                futures::future::ready(Ok(match inflate(&mut inflater, packet) {
+                    Ok(Some(packet)) => handle_packet(&shared, &heartbeat, encoding, packet),
+                    Ok(None) => None,
+                    Err(err) => {
+                        // the compression context is broken, so the connection is unusable
+                        eprintln!("{}, reconnecting", err);
+                        Some(Incoming::Reconnect(Duration::from_secs(0)))
+                    }
+                }))
+            })),
+            heartbeat_tx,
+            zombie_rx,
+            heartbeat_task,
+        }))
+    }
+}
+
+impl Drop for GatewayConnection {
+    fn drop(&mut self) {
+        self.state = ConnectionState::Closed;
+        self.shared.close();
+    }
+}
+
+impl GatewayHandle {
+    /// Send a command to the gateway, waiting until it has been written to the connection.
+    ///
+    /// Commands are queued to stay within the gateway's limit of 120 payloads per minute,
+    /// leaving room for heartbeats. Queued commands are kept across reconnects, and fail once
+    /// the connection has closed fatally or been dropped.
+    pub async fn send_command<T: serde::Serialize>(&self, op: u8, data: T) -> Result<(), Error> {
+        self.queue_command(op, data, None).await
+    }
+
+    async fn queue_command<T: serde::Serialize>(
+        &self,
+        op: u8,
+        data: T,
+        member_request: Option<String>,
+    ) -> Result<(), Error> {
+        let mut closed = self.shared.closed_rx.clone();
+        if *closed.borrow() {
+            return Err(Error::GatewayConnectionClosed);
+        }
+
+        let payload = serde_json::to_value(crate::DiscordBasePayload { op, d: data })
+            .map_err(Error::Serialization)?;
+        let (sent_tx, sent_rx) = tokio::sync::oneshot::channel();
+        self.shared
+            .command_tx
+            .send(Command {
+                payload,
+                member_request,
+                sent: sent_tx,
+            })
+            .map_err(|_| Error::GatewayConnectionClosed)?;
+
+        let closed = async move {
+            while !*closed.borrow() {
+                if closed.changed().await.is_err() {
+                    break;
+                }
+            }
+        };
+        tokio::select! {
+            res = sent_rx => res.map_err(|_| Error::GatewayConnectionClosed)?,
+            _ = closed => Err(Error::GatewayConnectionClosed),
+        }
+    }
+
+    /// Request members of a guild, waiting for all chunks of the response.
+    ///
+    /// Presences are only included if requested and the
+    /// [`Intents::GUILD_PRESENCES`](crate::Intents::GUILD_PRESENCES) intent is enabled.
+    pub async fn request_guild_members(
+        &self,
+        guild_id: &str,
+        query: MemberQuery<'_>,
+        presences: bool,
+    ) -> Result<GuildMembers, Error> {
+        #[derive(Serialize)]
+        struct RequestGuildMembers<'a> {
+            guild_id: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            query: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            limit: Option<u32>,
+            presences: bool,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            user_ids: Option<&'a [crate::Snowflake]>,
+            nonce: &'a str,
+        }
+
+        let (query, limit, user_ids) = match query {
+            MemberQuery::Prefix { query, limit } => (Some(query), Some(limit), None),
+            MemberQuery::UserIds(user_ids) => (None, None, Some(user_ids)),
+        };
+
+        let (nonce, members_rx) = self.shared.member_requests.start();
+        let res = self
+            .queue_command(
+                8,
+                RequestGuildMembers {
+                    guild_id,
+                    query,
+                    limit,
+                    presences,
+                    user_ids,
+                    nonce: &nonce,
+                },
+                Some(nonce.clone()),
+            )
+            .await;
+        if let Err(err) = res {
+            self.shared.member_requests.cancel(&nonce);
+            return Err(err);
+        }
+
+        // the request is dropped if its session ends before all chunks arrive
+        members_rx
+            .await
+            .map_err(|_| Error::GatewayConnectionClosed)?
+    }
+
+    /// Update the bot's presence, which is also used when identifying after a reconnect
+    pub async fn update_presence(&self, presence: Presence) -> Result<(), Error> {
+        *self.shared.presence.lock().unwrap() = Some(presence.clone());
+        self.send_command(3, presence).await
+    }
+
+    /// Round-trip time between the most recent heartbeat and its acknowledgement
+    pub fn latency(&self) -> Option<Duration> {
+        self.shared.latency.lock().unwrap().latest()
+    }
+
+    /// Average heartbeat round-trip time over the last few heartbeats
+    pub fn average_latency(&self) -> Option<Duration> {
+        self.shared.latency.lock().unwrap().average()
+    }
+
+    /// Number of reconnect attempts since the connection was last established
+    pub fn reconnect_attempts(&self) -> u32 {
+        self.shared.reconnect_attempts.load(Ordering::Relaxed)
     }
 }
 
@@ -162,99 +615,233 @@ impl Stream for GatewayConnection {
         mut self: Pin<&mut Self>,
         ctx: &mut futures::task::Context,
     ) -> futures::task::Poll<Option<Self::Item>> {
-        enum ConnPollRes {
-            NewState(ConnectionState),
-            Result(futures::task::Poll<Option<Result<Event, Error>>>),
-        }
-        let res = match self.state {
-            ConnectionState::Pending(ref mut fut) => {
-                let res = fut.as_mut().poll(ctx);
-                match res {
-                    futures::task::Poll::Ready(Ok(new_state)) => ConnPollRes::NewState(new_state),
-                    futures::task::Poll::Pending => {
-                        ConnPollRes::Result(futures::task::Poll::Pending)
-                    }
+        let this = &mut *self;
+        loop {
+            let new_state = match this.state {
+                ConnectionState::Pending(ref mut fut) => match fut.as_mut().poll(ctx) {
+                    futures::task::Poll::Ready(Ok(new_state)) => new_state,
                     futures::task::Poll::Ready(Err(err)) => {
-                        ConnPollRes::Result(futures::task::Poll::Ready(Some(Err(err))))
+                        this.state = this.reconnect(Duration::from_secs(0));
+                        return futures::task::Poll::Ready(Some(Err(err)));
                     }
-                }
-            }
-            ConnectionState::Connected(ref mut stream) => {
-                let res = stream.as_mut().poll_next(ctx);
-                match res {
-                    futures::task::Poll::Ready(None) => {
-                        // stream ended, reconnect
-                        println!("reconnecting");
-                        ConnPollRes::NewState(ConnectionState::Pending(Box::pin(
-                            GatewayConnection::connect(self.conn_params.clone(), None),
-                        )))
+                    futures::task::Poll::Pending => return futures::task::Poll::Pending,
+                },
+                ConnectionState::Connected(ref mut conn) => {
+                    if Pin::new(&mut conn.zombie_rx).poll(ctx).is_ready() {
+                        eprintln!("Heartbeat was not acknowledged, reconnecting");
+                        this.state = this.reconnect(Duration::from_secs(0));
+                        continue;
+                    }
+                    match conn.stream.as_mut().poll_next(ctx) {
+                        futures::task::Poll::Ready(Some(Ok(Incoming::Event(event)))) => {
+                            return futures::task::Poll::Ready(Some(Ok(event)));
+                        }
+                        futures::task::Poll::Ready(Some(Ok(Incoming::HeartbeatRequest))) => {
+                            // if the heartbeat task is gone, the stream will end soon anyway
+                            let _ = conn.heartbeat_tx.send(());
+                            continue;
+                        }
+                        futures::task::Poll::Ready(Some(Ok(Incoming::Reconnect(delay)))) => {
+                            eprintln!("reconnecting");
+                            this.reconnect(delay)
+                        }
+                        futures::task::Poll::Ready(Some(Ok(Incoming::Fatal(err)))) => {
+                            this.state = ConnectionState::Closed;
+                            this.shared.close();
+                            return futures::task::Poll::Ready(Some(Err(err)));
+                        }
+                        futures::task::Poll::Ready(None) => {
+                            // stream ended, reconnect
+                            eprintln!("reconnecting");
+                            this.reconnect(Duration::from_secs(0))
+                        }
+                        futures::task::Poll::Ready(Some(Err(err))) => {
+                            return futures::task::Poll::Ready(Some(Err(err)));
+                        }
+                        futures::task::Poll::Pending => return futures::task::Poll::Pending,
                     }
-                    other => ConnPollRes::Result(other),
                 }
-            }
-        };
-        match res {
-            ConnPollRes::NewState(state) => {
-                self.state = state;
-                self.poll_next(ctx)
-            }
-            ConnPollRes::Result(res) => res,
+                ConnectionState::Closed => return futures::task::Poll::Ready(None),
+            };
+            this.state = new_state;
         }
     }
 }
 
+/// Exponential backoff with jitter, no delay for the first attempt
+fn backoff_delay(attempt: u32, max: Duration) -> Duration {
+    if attempt == 0 {
+        return Duration::from_secs(0);
+    }
+    let delay = RECONNECT_BASE_DELAY
+        .checked_mul(1 << (attempt - 1).min(16))
+        .map_or(max, |delay| delay.min(max));
+    delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+/// Add the version query parameter to a gateway URL
+pub(crate) fn with_gateway_params(mut url: url::Url) -> url::Url {
+    url.query_pairs_mut().append_pair("v", GATEWAY_VERSION);
+    url
+}
+
+/// Decompress a binary message if using transport compression,
+/// returning `None` until a complete payload has arrived
+fn inflate(
+    inflater: &mut Option<Inflater>,
+    msg: tungstenite::Message,
+) -> Result<Option<tungstenite::Message>, Error> {
+    match (msg, inflater) {
+        (tungstenite::Message::Binary(data), Some(inflater)) => {
+            Ok(inflater.push(&data)?.map(tungstenite::Message::Binary))
+        }
+        (msg, _) => Ok(Some(msg)),
+    }
+}
+
 fn handle_packet(
-    session_info: &Arc<Mutex<Option<ReconnectInfo>>>,
+    shared: &Shared,
+    heartbeat: &Mutex<HeartbeatState>,
+    encoding: Encoding,
     msg: tungstenite::Message,
-) -> Option<Event> {
-    if let tungstenite::Message::Text(text) = msg {
-        #[derive(Deserialize)]
-        struct RecvPayload<'a> {
-            pub op: u8,
-            pub d: serde_json::Value,
-            pub s: Option<u64>,
-            pub t: Option<&'a str>,
+) -> Option<Incoming> {
+    let data = match msg {
+        tungstenite::Message::Text(text) => text.into_bytes(),
+        tungstenite::Message::Binary(data) => data,
+        tungstenite::Message::Close(frame) => return handle_close(shared, frame),
+        msg => {
+            eprintln!("Unexpected message type: {:?}", msg);
+            return None;
         }
-        match serde_json::from_str::<RecvPayload>(&text) {
-            Err(err) => {
-                eprintln!("Failed to parse packet: {:?}", err);
-                None
-            }
-            Ok(packet) => {
-                match packet.op {
-                    0 => {
-                        if let Some(seq) = packet.s {
-                            if let Some(ref mut info) = *session_info.lock().unwrap() {
-                                info.last_event = seq;
-                            }
+    };
+    #[derive(Deserialize)]
+    struct RecvPayload {
+        pub op: u8,
+        pub d: serde_json::Value,
+        pub s: Option<u64>,
+        pub t: Option<String>,
+    }
+    match encoding.decode::<RecvPayload>(&data) {
+        Err(err) => {
+            eprintln!("Failed to parse packet: {:?}", err);
+            None
+        }
+        Ok(packet) => {
+            match packet.op {
+                0 => {
+                    if let Some(seq) = packet.s {
+                        shared.session.lock().unwrap().seq = Some(seq);
+                    }
+                    if let Some("READY") | Some("RESUMED") = packet.t.as_deref() {
+                        shared.reconnect_attempts.store(0, Ordering::Relaxed);
+                    }
+                    let event = match packet.t.as_deref() {
+                        Some("GUILD_MEMBERS_CHUNK")
+                            if shared.member_requests.handle_chunk(&packet.d) =>
+                        {
+                            None
                         }
-                        match packet.t {
-                            Some(ref t) => handle_event(&t, packet.d),
-                            None => {
-                                eprintln!("Missing event type");
-                                None
-                            }
+                        Some(t) => handle_event(t, packet.d, packet.s),
+                        None => {
+                            eprintln!("Missing event type");
+                            None
                         }
+                    };
+                    if let Some(Event::Ready(ref data)) = event {
+                        let mut session = shared.session.lock().unwrap();
+                        session.session_id = Some(data.session_id.clone());
+                        session.resume_url =
+                            data.resume_gateway_url.as_deref().and_then(
+                                |url| match url::Url::parse(url) {
+                                    Ok(url) => Some(with_gateway_params(url)),
+                                    Err(err) => {
+                                        eprintln!("Failed to parse resume gateway URL: {:?}", err);
+                                        None
+                                    }
+                                },
+                            );
+                        *shared.unavailable_guilds.lock().unwrap() =
+                            data.guilds.iter().map(|guild| guild.id.clone()).collect();
                     }
-                    11 => {
-                        // heartbeat ACK
-                        // potentially useful, but ignored for now
-                        None
+                    let event = match event {
+                        Some(Event::GuildCreate(guild))
+                            if shared.unavailable_guilds.lock().unwrap().remove(&guild.id) =>
+                        {
+                            Some(Event::GuildAvailable(guild))
+                        }
+                        Some(Event::GuildUnavailable(guild)) => {
+                            shared
+                                .unavailable_guilds
+                                .lock()
+                                .unwrap()
+                                .insert(guild.id.clone());
+                            Some(Event::GuildUnavailable(guild))
+                        }
+                        Some(Event::GuildDelete(guild)) => {
+                            shared.unavailable_guilds.lock().unwrap().remove(&guild.id);
+                            Some(Event::GuildDelete(guild))
+                        }
+                        event => event,
+                    };
+                    event.map(Incoming::Event)
+                }
+                1 => Some(Incoming::HeartbeatRequest),
+                7 => {
+                    // server asked us to reconnect, session can be resumed
+                    Some(Incoming::Reconnect(Duration::from_secs(0)))
+                }
+                9 => {
+                    if packet.d.as_bool().unwrap_or(false) {
+                        Some(Incoming::Reconnect(Duration::from_secs(0)))
+                    } else {
+                        // session is gone, re-identify after a random 1-5 second delay
+                        *shared.session.lock().unwrap() = Default::default();
+                        Some(Incoming::Reconnect(Duration::from_millis(
+                            rand::thread_rng().gen_range(1000..=5000),
+                        )))
                     }
-                    op => {
-                        eprintln!("Unrecognized packet op: {}", op);
-                        None
+                }
+                11 => {
+                    let mut heartbeat = heartbeat.lock().unwrap();
+                    heartbeat.acked = true;
+                    if let Some(sent) = heartbeat.last_sent.take() {
+                        shared.latency.lock().unwrap().record(sent.elapsed());
                     }
+                    None
+                }
+                op => {
+                    eprintln!("Unrecognized packet op: {}", op);
+                    None
                 }
             }
         }
+    }
+}
+
+fn handle_close(
+    shared: &Shared,
+    frame: Option<tungstenite::protocol::CloseFrame>,
+) -> Option<Incoming> {
+    let frame = match frame {
+        Some(frame) => frame,
+        None => return Some(Incoming::Reconnect(Duration::from_secs(0))),
+    };
+    let code = CloseCode::from(u16::from(frame.code));
+    if code.is_fatal() {
+        Some(Incoming::Fatal(Error::GatewayClosed {
+            code,
+            reason: frame.reason.into_owned(),
+        }))
     } else {
-        eprintln!("Unexpected message type: {:?}", msg);
-        None
+        eprintln!("Gateway closed with code {}: {}", code.code(), frame.reason);
+        if code.invalidates_session() {
+            *shared.session.lock().unwrap() = Default::default();
+        }
+        Some(Incoming::Reconnect(Duration::from_secs(0)))
     }
 }
 
-fn handle_event(t: &str, d: serde_json::Value) -> Option<Event> {
+fn handle_event(t: &str, d: serde_json::Value, seq: Option<u64>) -> Option<Event> {
     match t {
         "READY" => {
             let user = match serde_json::from_value(d["user"].clone()) {
@@ -264,7 +851,28 @@ fn handle_event(t: &str, d: serde_json::Value) -> Option<Event> {
                 }
                 Ok(x) => x,
             };
-            Some(Event::Ready(crate::events::ReadyData { user }))
+            let session_id = match d["session_id"].as_str() {
+                Some(x) => x.to_owned(),
+                None => {
+                    eprintln!("Missing session ID in ready");
+                    return None;
+                }
+            };
+            let resume_gateway_url = d["resume_gateway_url"].as_str().map(ToOwned::to_owned);
+            let guilds = serde_json::from_value(d["guilds"].clone()).unwrap_or_else(|err| {
+                eprintln!("Failed to parse ready guilds: {:?}", err);
+                Vec::new()
+            });
+            Some(Event::Ready(crate::events::ReadyData {
+                user,
+                session_id,
+                resume_gateway_url,
+                guilds,
+            }))
+        }
+        "RESUMED" => {
+            // session resumed, missed events are replayed by the gateway
+            None
         }
         "MESSAGE_CREATE" => match serde_json::from_value(d) {
             Err(err) => {
@@ -273,9 +881,187 @@ fn handle_event(t: &str, d: serde_json::Value) -> Option<Event> {
             }
             Ok(data) => Some(Event::MessageCreate(data)),
         },
-        _ => {
-            eprintln!("Unrecognized event type: {}", t);
-            None
+        "MESSAGE_UPDATE" => match serde_json::from_value(d) {
+            Err(err) => {
+                eprintln!("Failed to parse message update: {:?}", err);
+                None
+            }
+            Ok(data) => Some(Event::MessageUpdate(data)),
+        },
+        "MESSAGE_DELETE" => match serde_json::from_value(d) {
+            Err(err) => {
+                eprintln!("Failed to parse message delete: {:?}", err);
+                None
+            }
+            Ok(data) => Some(Event::MessageDelete(data)),
+        },
+        "MESSAGE_DELETE_BULK" => match serde_json::from_value(d) {
+            Err(err) => {
+                eprintln!("Failed to parse message bulk delete: {:?}", err);
+                None
+            }
+            Ok(data) => Some(Event::MessageDeleteBulk(data)),
+        },
+        "GUILD_CREATE" | "GUILD_DELETE" if d["unavailable"].as_bool() == Some(true) => {
+            match serde_json::from_value(d) {
+                Err(err) => {
+                    eprintln!("Failed to parse unavailable guild: {:?}", err);
+                    None
+                }
+                Ok(data) => Some(Event::GuildUnavailable(data)),
+            }
         }
+        "GUILD_CREATE" => match serde_json::from_value(d) {
+            Err(err) => {
+                eprintln!("Failed to parse guild: {:?}", err);
+                None
+            }
+            Ok(data) => Some(Event::GuildCreate(data)),
+        },
+        "GUILD_UPDATE" => match serde_json::from_value(d) {
+            Err(err) => {
+                eprintln!("Failed to parse guild update: {:?}", err);
+                None
+            }
+            Ok(data) => Some(Event::GuildUpdate(data)),
+        },
+        "GUILD_DELETE" => match serde_json::from_value(d) {
+            Err(err) => {
+                eprintln!("Failed to parse guild delete: {:?}", err);
+                None
+            }
+            Ok(data) => Some(Event::GuildDelete(data)),
+        },
+        "GUILD_MEMBER_ADD" => match serde_json::from_value(d) {
+            Err(err) => {
+                eprintln!("Failed to parse guild member add: {:?}", err);
+                None
+            }
+            Ok(data) => Some(Event::GuildMemberAdd(data)),
+        },
+        "GUILD_MEMBER_UPDATE" => match serde_json::from_value(d) {
+            Err(err) => {
+                eprintln!("Failed to parse guild member update: {:?}", err);
+                None
+            }
+            Ok(data) => Some(Event::GuildMemberUpdate(data)),
+        },
+        "GUILD_MEMBER_REMOVE" => match serde_json::from_value(d) {
+            Err(err) => {
+                eprintln!("Failed to parse guild member remove: {:?}", err);
+                None
+            }
+            Ok(data) => Some(Event::GuildMemberRemove(data)),
+        },
+        _ => Some(Event::Unknown {
+            name: t.to_owned(),
+            data: d,
+            seq,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_with_jitter() {
+        let max = Duration::from_secs(60);
+        assert_eq!(backoff_delay(0, max), Duration::from_secs(0));
+        for attempt in 1..=6 {
+            let base = RECONNECT_BASE_DELAY * (1 << (attempt - 1));
+            for _ in 0..20 {
+                let delay = backoff_delay(attempt, max);
+                assert!(delay >= base / 2 && delay <= base, "{:?}", delay);
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let max = Duration::from_secs(60);
+        for attempt in [7, 17, 40, u32::MAX] {
+            let delay = backoff_delay(attempt, max);
+            assert!(delay >= max / 2 && delay <= max, "{:?}", delay);
+        }
+        let delay = backoff_delay(3, Duration::from_millis(100));
+        assert!(delay <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn tracks_unavailable_guilds() {
+        let shared = Shared::new(None);
+        let heartbeat = Mutex::new(HeartbeatState {
+            acked: true,
+            last_sent: None,
+        });
+        let mut seq = 0;
+        let mut dispatch = |t: &str, d: serde_json::Value| {
+            seq += 1;
+            let packet = serde_json::json!({"op": 0, "s": seq, "t": t, "d": d});
+            let msg = tungstenite::Message::Text(packet.to_string());
+            match handle_packet(&shared, &heartbeat, Encoding::Json, msg) {
+                Some(Incoming::Event(event)) => event,
+                _ => panic!("No event for {}", t),
+            }
+        };
+        let guild = |id: &str| {
+            serde_json::json!({
+                "id": id,
+                "name": "guild",
+                "icon": null,
+                "owner_id": "9",
+                "roles": [],
+                "emojis": [],
+            })
+        };
+
+        let event = dispatch(
+            "READY",
+            serde_json::json!({
+                "user": {
+                    "id": "9",
+                    "username": "bot",
+                    "discriminator": "0",
+                    "avatar": null,
+                    "bot": true,
+                    "mfa_enabled": false,
+                    "verified": true,
+                },
+                "session_id": "session",
+                "guilds": [{"id": "1", "unavailable": true}, {"id": "2", "unavailable": true}],
+            }),
+        );
+        assert!(matches!(event, Event::Ready(ref data) if data.guilds.len() == 2));
+
+        // lazy load of a guild from READY
+        let event = dispatch("GUILD_CREATE", guild("1"));
+        assert!(matches!(event, Event::GuildAvailable(ref guild) if guild.id == "1"));
+
+        // joined a new guild
+        let event = dispatch("GUILD_CREATE", guild("3"));
+        assert!(matches!(event, Event::GuildCreate(ref guild) if guild.id == "3"));
+
+        // outage and recovery
+        let event = dispatch(
+            "GUILD_DELETE",
+            serde_json::json!({"id": "1", "unavailable": true}),
+        );
+        assert!(matches!(event, Event::GuildUnavailable(ref guild) if guild.id == "1"));
+        let event = dispatch("GUILD_CREATE", guild("1"));
+        assert!(matches!(event, Event::GuildAvailable(ref guild) if guild.id == "1"));
+
+        // removed from a guild
+        let event = dispatch("GUILD_DELETE", serde_json::json!({"id": "3"}));
+        assert!(matches!(event, Event::GuildDelete(ref guild) if guild.id == "3"));
+        let event = dispatch("GUILD_CREATE", guild("3"));
+        assert!(matches!(event, Event::GuildCreate(ref guild) if guild.id == "3"));
+
+        // the other guild from READY is still loading
+        assert_eq!(
+            *shared.unavailable_guilds.lock().unwrap(),
+            std::iter::once("2".to_owned()).collect()
+        );
     }
 }
@@ -1,3 +1,6 @@
+use serde_derive::Deserialize;
+use std::fmt;
+use std::time::Duration;
 use tokio_tungstenite::tungstenite;
 
 #[derive(Debug)]
@@ -5,18 +8,294 @@ use tokio_tungstenite::tungstenite;
 pub enum Error {
     /// Failed to authenticate with the API
     AuthenticationFailed,
+    /// The API responded with a Discord error object
+    Api {
+        /// HTTP status of the response
+        status: hyper::StatusCode,
+        /// Parsed error body
+        error: ApiError,
+    },
+    /// The API responded with an unsuccessful status and no recognizable error body
+    Status {
+        /// HTTP status of the response
+        status: hyper::StatusCode,
+        /// Raw response body
+        body: String,
+    },
+    /// A request was rate limited for longer than the configured timeout
+    RateLimited {
+        /// How long until the request may be retried
+        retry_after: Duration,
+        /// Whether the global rate limit was hit, rather than a per-route one
+        global: bool,
+    },
+    /// The gateway closed the connection and it cannot be reestablished
+    GatewayClosed {
+        /// Reason code for the close
+        code: CloseCode,
+        /// Reason given for the close
+        reason: String,
+    },
+    /// The gateway connection closed for good or was dropped before a command or request completed
+    GatewayConnectionClosed,
+    /// A REST request did not complete within the configured timeout
+    Timeout,
+    /// Failed to serialize or deserialize a payload
+    Serialization(serde_json::Error),
+    /// Failed to construct an HTTP request
+    Request(hyper::http::Error),
+    /// HTTP transport failure
+    Http(hyper::Error),
+    /// WebSocket transport failure
+    WebSocket(Box<tungstenite::Error>),
     /// Some other error
     Other(String),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::AuthenticationFailed => write!(f, "Failed to authenticate with the API"),
+            Error::Api { status, error } => write!(
+                f,
+                "API error {} ({}): {}",
+                error.code, status, error.message
+            ),
+            Error::Status { status, body } => {
+                write!(f, "Error in remote response ({}): {}", status, body)
+            }
+            Error::RateLimited {
+                retry_after,
+                global,
+            } => write!(
+                f,
+                "{} for {:?}",
+                if *global {
+                    "Globally rate limited"
+                } else {
+                    "Rate limited"
+                },
+                retry_after
+            ),
+            Error::GatewayClosed { code, reason } => write!(
+                f,
+                "Gateway closed with code {} ({:?}): {}",
+                code.code(),
+                code,
+                reason
+            ),
+            Error::GatewayConnectionClosed => write!(f, "Gateway connection is closed"),
+            Error::Timeout => write!(f, "Request timed out"),
+            Error::Serialization(_) => write!(f, "Failed to (de)serialize payload"),
+            Error::Request(_) => write!(f, "Failed to create request"),
+            Error::Http(_) => write!(f, "HTTP failure"),
+            Error::WebSocket(_) => write!(f, "WebSocket failure"),
+            Error::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Serialization(e) => Some(e),
+            Error::Request(e) => Some(e),
+            Error::Http(e) => Some(e),
+            Error::WebSocket(e) => Some(&**e),
+            _ => None,
+        }
+    }
+}
+
 impl From<hyper::Error> for Error {
     fn from(e: hyper::Error) -> Self {
-        Error::Other(format!("HTTP Failure: {:?}", e))
+        Error::Http(e)
+    }
+}
+
+impl From<hyper::http::Error> for Error {
+    fn from(e: hyper::http::Error) -> Self {
+        Error::Request(e)
     }
 }
 
 impl From<tungstenite::Error> for Error {
     fn from(e: tungstenite::Error) -> Self {
-        Error::Other(format!("WebSocket Failure: {:?}", e))
+        Error::WebSocket(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Serialization(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// [Close code](https://discord.com/developers/docs/topics/opcodes-and-status-codes#gateway-gateway-close-event-codes)
+/// sent by the gateway when closing a connection
+pub enum CloseCode {
+    /// 4000: Unknown error, a reconnect is possible
+    UnknownError,
+    /// 4001: An invalid opcode or payload was sent
+    UnknownOpcode,
+    /// 4002: A payload could not be decoded
+    DecodeError,
+    /// 4003: A payload was sent before identifying
+    NotAuthenticated,
+    /// 4004: The token is invalid
+    AuthenticationFailed,
+    /// 4005: More than one IDENTIFY was sent
+    AlreadyAuthenticated,
+    /// 4007: The sequence sent when resuming was invalid
+    InvalidSeq,
+    /// 4008: Payloads were sent too quickly
+    RateLimited,
+    /// 4009: The session timed out
+    SessionTimedOut,
+    /// 4010: An invalid shard was sent when identifying
+    InvalidShard,
+    /// 4011: The bot is in too many guilds to connect without sharding
+    ShardingRequired,
+    /// 4012: An invalid gateway version was requested
+    InvalidApiVersion,
+    /// 4013: Invalid intents were sent
+    InvalidIntents,
+    /// 4014: Privileged intents were sent without being enabled
+    DisallowedIntents,
+    /// Any other code
+    Other(u16),
+}
+
+impl CloseCode {
+    /// Numeric value of the close code
+    pub fn code(self) -> u16 {
+        match self {
+            CloseCode::UnknownError => 4000,
+            CloseCode::UnknownOpcode => 4001,
+            CloseCode::DecodeError => 4002,
+            CloseCode::NotAuthenticated => 4003,
+            CloseCode::AuthenticationFailed => 4004,
+            CloseCode::AlreadyAuthenticated => 4005,
+            CloseCode::InvalidSeq => 4007,
+            CloseCode::RateLimited => 4008,
+            CloseCode::SessionTimedOut => 4009,
+            CloseCode::InvalidShard => 4010,
+            CloseCode::ShardingRequired => 4011,
+            CloseCode::InvalidApiVersion => 4012,
+            CloseCode::InvalidIntents => 4013,
+            CloseCode::DisallowedIntents => 4014,
+            CloseCode::Other(code) => code,
+        }
+    }
+
+    /// Whether reconnecting is pointless without changing the configuration
+    pub fn is_fatal(self) -> bool {
+        matches!(
+            self,
+            CloseCode::AuthenticationFailed
+                | CloseCode::InvalidShard
+                | CloseCode::ShardingRequired
+                | CloseCode::InvalidApiVersion
+                | CloseCode::InvalidIntents
+                | CloseCode::DisallowedIntents
+        )
+    }
+
+    /// Whether the session is gone, so a reconnect must re-identify instead of resuming
+    pub fn invalidates_session(self) -> bool {
+        matches!(self, CloseCode::InvalidSeq | CloseCode::SessionTimedOut)
+    }
+}
+
+impl From<u16> for CloseCode {
+    fn from(code: u16) -> Self {
+        match code {
+            4000 => CloseCode::UnknownError,
+            4001 => CloseCode::UnknownOpcode,
+            4002 => CloseCode::DecodeError,
+            4003 => CloseCode::NotAuthenticated,
+            4004 => CloseCode::AuthenticationFailed,
+            4005 => CloseCode::AlreadyAuthenticated,
+            4007 => CloseCode::InvalidSeq,
+            4008 => CloseCode::RateLimited,
+            4009 => CloseCode::SessionTimedOut,
+            4010 => CloseCode::InvalidShard,
+            4011 => CloseCode::ShardingRequired,
+            4012 => CloseCode::InvalidApiVersion,
+            4013 => CloseCode::InvalidIntents,
+            4014 => CloseCode::DisallowedIntents,
+            code => CloseCode::Other(code),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// Error object returned by the API
+/// ([relevant Discord docs](https://discord.com/developers/docs/reference#error-messages))
+pub struct ApiError {
+    /// [JSON error code](https://discord.com/developers/docs/topics/opcodes-and-status-codes#json-json-error-codes)
+    pub code: u32,
+    /// Human-readable error message
+    pub message: String,
+    /// Nested errors for individual request fields
+    #[serde(default)]
+    pub errors: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+/// Error for a single field of a request, from [`ApiError::field_errors`]
+pub struct FieldError {
+    /// Path to the field, e.g. `embeds.0.title`
+    #[serde(skip)]
+    pub path: String,
+    /// Error code, e.g. `BASE_TYPE_REQUIRED`
+    pub code: String,
+    /// Human-readable error message
+    pub message: String,
+}
+
+#[allow(missing_docs)]
+impl ApiError {
+    pub const UNKNOWN_CHANNEL: u32 = 10003;
+    pub const UNKNOWN_GUILD: u32 = 10004;
+    pub const UNKNOWN_MEMBER: u32 = 10007;
+    pub const UNKNOWN_MESSAGE: u32 = 10008;
+    pub const UNKNOWN_USER: u32 = 10013;
+    pub const MISSING_ACCESS: u32 = 50001;
+    pub const CANNOT_SEND_EMPTY_MESSAGE: u32 = 50006;
+    pub const CANNOT_SEND_TO_USER: u32 = 50007;
+    pub const MISSING_PERMISSIONS: u32 = 50013;
+    pub const INVALID_FORM_BODY: u32 = 50035;
+}
+
+impl ApiError {
+    /// Flatten the nested field errors into a list
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        fn collect(value: &serde_json::Value, path: &str, out: &mut Vec<FieldError>) {
+            if let serde_json::Value::Object(map) = value {
+                for (key, value) in map {
+                    if key == "_errors" {
+                        if let Ok(errors) = serde_json::from_value::<Vec<FieldError>>(value.clone())
+                        {
+                            out.extend(errors.into_iter().map(|error| FieldError {
+                                path: path.to_owned(),
+                                ..error
+                            }));
+                        }
+                    } else if path.is_empty() {
+                        collect(value, key, out);
+                    } else {
+                        collect(value, &format!("{}.{}", path, key), out);
+                    }
+                }
+            }
+        }
+
+        let mut out = Vec::new();
+        if let Some(ref errors) = self.errors {
+            collect(errors, "", &mut out);
+        }
+        out
     }
 }
@@ -1,4 +1,4 @@
-use crate::types::{Message, Snowflake, User};
+use crate::types::{Guild, Member, Message, Snowflake, UnavailableGuild, User};
 use serde_derive::Deserialize;
 
 #[derive(Debug)]
@@ -8,6 +8,37 @@ pub enum Event {
     Ready(ReadyData),
     /// Message received
     MessageCreate(Message),
+    /// Message edited, or embeds added to it
+    MessageUpdate(PartialMessage),
+    /// Message deleted
+    MessageDelete(MessageDeleteData),
+    /// Multiple messages deleted at once
+    MessageDeleteBulk(MessageDeleteBulkData),
+    /// Guild from READY was loaded, or became available again after an outage
+    GuildAvailable(Guild),
+    /// Bot joined a new guild
+    GuildCreate(Guild),
+    /// Guild was updated
+    GuildUpdate(Guild),
+    /// Guild became unavailable due to an outage
+    GuildUnavailable(UnavailableGuild),
+    /// Bot was removed from a guild, or the guild was deleted
+    GuildDelete(UnavailableGuild),
+    /// User joined a guild, requires [`Intents::GUILD_MEMBERS`](crate::Intents::GUILD_MEMBERS)
+    GuildMemberAdd(GuildMemberData),
+    /// Guild member was updated, requires [`Intents::GUILD_MEMBERS`](crate::Intents::GUILD_MEMBERS)
+    GuildMemberUpdate(GuildMemberData),
+    /// User left or was removed from a guild, requires [`Intents::GUILD_MEMBERS`](crate::Intents::GUILD_MEMBERS)
+    GuildMemberRemove(GuildMemberRemoveData),
+    /// Event not modeled by this library
+    Unknown {
+        /// Event name, e.g. `TYPING_START`
+        name: String,
+        /// Raw event data
+        data: serde_json::Value,
+        /// Sequence number of the event
+        seq: Option<u64>,
+    },
 }
 
 #[derive(Debug)]
@@ -15,6 +46,83 @@ pub enum Event {
 pub struct ReadyData {
     /// Authenticated user info
     pub user: Myself,
+    /// ID of the gateway session, used for resuming
+    pub session_id: String,
+    /// Gateway URL to use when resuming this session
+    pub resume_gateway_url: Option<String>,
+    /// Guilds the bot is in, each followed by [`Event::GuildAvailable`] once it has loaded
+    pub guilds: Vec<UnavailableGuild>,
+}
+
+#[derive(Debug, Deserialize)]
+/// Object contained in [`Event::MessageUpdate`], with only the `id` and `channel_id` guaranteed
+/// ([relevant Discord docs](https://discord.com/developers/docs/topics/gateway-events#message-update))
+pub struct PartialMessage {
+    /// Message ID
+    pub id: Snowflake,
+    /// ID of the origin channel
+    pub channel_id: Snowflake,
+    /// ID of the guild, if sent in one
+    pub guild_id: Option<Snowflake>,
+    /// Author of the message
+    pub author: Option<User>,
+    /// Text content of the message
+    pub content: Option<String>,
+    /// When this message was sent
+    pub timestamp: Option<String>,
+    /// When this message was last edited
+    pub edited_timestamp: Option<String>,
+    /// Whether this is a TTS message
+    pub tts: Option<bool>,
+    /// Whether this message mentions everyone
+    pub mention_everyone: Option<bool>,
+    /// Users specifically mentioned in the message
+    pub mentions: Option<Vec<User>>,
+    /// Roles specifically mentioned in this message
+    pub mention_roles: Option<Vec<Snowflake>>,
+}
+
+#[derive(Debug, Deserialize)]
+/// Object contained in [`Event::MessageDelete`]
+pub struct MessageDeleteData {
+    /// ID of the deleted message
+    pub id: Snowflake,
+    /// ID of the channel the message was in
+    pub channel_id: Snowflake,
+    /// ID of the guild, if the message was in one
+    pub guild_id: Option<Snowflake>,
+}
+
+#[derive(Debug, Deserialize)]
+/// Object contained in [`Event::MessageDeleteBulk`]
+pub struct MessageDeleteBulkData {
+    /// IDs of the deleted messages
+    pub ids: Vec<Snowflake>,
+    /// ID of the channel the messages were in
+    pub channel_id: Snowflake,
+    /// ID of the guild, if the messages were in one
+    pub guild_id: Option<Snowflake>,
+}
+
+#[derive(Debug, Deserialize)]
+/// Object contained in [`Event::GuildMemberAdd`] and [`Event::GuildMemberUpdate`].
+///
+/// Updates don't include `deaf` and `mute`, so those are always `false` there.
+pub struct GuildMemberData {
+    /// ID of the guild
+    pub guild_id: Snowflake,
+    /// The member that was added or updated
+    #[serde(flatten)]
+    pub member: Member,
+}
+
+#[derive(Debug, Deserialize)]
+/// Object contained in [`Event::GuildMemberRemove`]
+pub struct GuildMemberRemoveData {
+    /// ID of the guild
+    pub guild_id: Snowflake,
+    /// The user that was removed
+    pub user: User,
 }
 
 #[derive(Debug, Deserialize)]
@@ -39,13 +147,13 @@ pub struct Myself {
     pub email: Option<String>,
 }
 
-impl Into<User> for Myself {
-    fn into(self) -> User {
+impl From<Myself> for User {
+    fn from(myself: Myself) -> User {
         User {
-            id: self.id,
-            username: self.username,
-            discriminator: self.discriminator,
-            avatar: self.avatar,
+            id: myself.id,
+            username: myself.username,
+            discriminator: myself.discriminator,
+            avatar: myself.avatar,
         }
     }
 }
@@ -0,0 +1,77 @@
+use serde::{Serialize, Serializer};
+
+bitflags::bitflags! {
+    /// Set of [gateway intents](https://discord.com/developers/docs/topics/gateway#gateway-intents),
+    /// selecting which events will be received
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct Intents: u64 {
+        /// Guild creation, updates, deletion, roles, channels, and threads
+        const GUILDS = 1 << 0;
+        /// Guild member additions, updates, and removals (privileged)
+        const GUILD_MEMBERS = 1 << 1;
+        /// Bans and audit log entries
+        const GUILD_MODERATION = 1 << 2;
+        /// Emoji and sticker updates
+        const GUILD_EMOJIS_AND_STICKERS = 1 << 3;
+        /// Integration updates
+        const GUILD_INTEGRATIONS = 1 << 4;
+        /// Webhook updates
+        const GUILD_WEBHOOKS = 1 << 5;
+        /// Invite creation and deletion
+        const GUILD_INVITES = 1 << 6;
+        /// Voice state updates
+        const GUILD_VOICE_STATES = 1 << 7;
+        /// Presence updates (privileged)
+        const GUILD_PRESENCES = 1 << 8;
+        /// Messages sent in guilds
+        const GUILD_MESSAGES = 1 << 9;
+        /// Reactions on messages in guilds
+        const GUILD_MESSAGE_REACTIONS = 1 << 10;
+        /// Typing indicators in guilds
+        const GUILD_MESSAGE_TYPING = 1 << 11;
+        /// Messages sent in DMs
+        const DIRECT_MESSAGES = 1 << 12;
+        /// Reactions on messages in DMs
+        const DIRECT_MESSAGE_REACTIONS = 1 << 13;
+        /// Typing indicators in DMs
+        const DIRECT_MESSAGE_TYPING = 1 << 14;
+        /// Content of messages not mentioning the bot (privileged)
+        const MESSAGE_CONTENT = 1 << 15;
+        /// Scheduled event creation, updates, deletion, and attendance
+        const GUILD_SCHEDULED_EVENTS = 1 << 16;
+        /// Auto moderation rule changes
+        const AUTO_MODERATION_CONFIGURATION = 1 << 20;
+        /// Auto moderation actions
+        const AUTO_MODERATION_EXECUTION = 1 << 21;
+        /// Poll votes in guilds
+        const GUILD_MESSAGE_POLLS = 1 << 24;
+        /// Poll votes in DMs
+        const DIRECT_MESSAGE_POLLS = 1 << 25;
+    }
+}
+
+impl Intents {
+    /// Intents which must be enabled in the developer portal before use
+    pub const fn privileged() -> Self {
+        Self::GUILD_MEMBERS
+            .union(Self::GUILD_PRESENCES)
+            .union(Self::MESSAGE_CONTENT)
+    }
+
+    /// All intents which can be used without extra approval
+    pub const fn non_privileged() -> Self {
+        Self::all().difference(Self::privileged())
+    }
+}
+
+impl Default for Intents {
+    fn default() -> Self {
+        Self::non_privileged()
+    }
+}
+
+impl Serialize for Intents {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.bits())
+    }
+}
@@ -8,12 +8,19 @@ mod client;
 mod error;
 /// Events and related objects
 pub mod events;
+mod intents;
+mod presence;
 mod types;
 
 pub use builder::{EmbedBuilder, MessageBuilder};
-pub use client::{Client, GatewayConnection, ListAnchor};
-pub use error::Error;
+pub use client::{
+    Client, ClientBuilder, GatewayConnection, GatewayHandle, GuildMembers, ListAnchor, MemberQuery,
+    ShardManager,
+};
+pub use error::{ApiError, CloseCode, Error, FieldError};
 pub use events::Event;
+pub use intents::Intents;
+pub use presence::{Activity, ActivityType, Presence, Status, UserPresence};
 pub use types::*;
 
 use serde_derive::{Deserialize, Serialize};
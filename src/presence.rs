@@ -0,0 +1,208 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, PartialEq, Eq, serde_derive::Serialize)]
+/// Presence shown for the bot user
+/// ([relevant Discord docs](https://discord.com/developers/docs/topics/gateway-events#update-presence))
+pub struct Presence {
+    /// Unix time in milliseconds since the bot went idle, if idle
+    pub since: Option<u64>,
+    /// Activities shown for the bot
+    pub activities: Vec<Activity>,
+    /// Online status
+    pub status: Status,
+    /// Whether the bot is AFK
+    pub afk: bool,
+}
+
+impl Presence {
+    /// Create a presence with the given status and no activities
+    pub fn new(status: Status) -> Self {
+        Self {
+            since: None,
+            activities: Vec::new(),
+            status,
+            afk: false,
+        }
+    }
+
+    /// Add an activity to show
+    pub fn with_activity(mut self, activity: Activity) -> Self {
+        self.activities.push(activity);
+        self
+    }
+}
+
+impl Default for Presence {
+    fn default() -> Self {
+        Self::new(Status::Online)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde_derive::Serialize, serde_derive::Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Online status of a user
+pub enum Status {
+    /// Online
+    Online,
+    /// Do Not Disturb
+    Dnd,
+    /// Idle
+    Idle,
+    /// Shown as offline
+    Invisible,
+    /// Offline
+    Offline,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde_derive::Serialize, serde_derive::Deserialize)]
+/// Activity shown in a presence
+/// ([relevant Discord docs](https://discord.com/developers/docs/topics/gateway-events#activity-object))
+pub struct Activity {
+    /// Name of the activity
+    pub name: String,
+    /// Kind of activity
+    #[serde(rename = "type")]
+    pub kind: ActivityType,
+    /// Stream URL, for [`ActivityType::Streaming`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Custom status text, or the party status for other activities
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+}
+
+impl Activity {
+    /// Create an activity of the given kind
+    pub fn new(kind: ActivityType, name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind,
+            url: None,
+            state: None,
+        }
+    }
+
+    /// "Playing {name}"
+    pub fn playing(name: &str) -> Self {
+        Self::new(ActivityType::Playing, name)
+    }
+
+    /// "Streaming {name}", linking to a Twitch or YouTube URL
+    pub fn streaming(name: &str, url: &str) -> Self {
+        Self {
+            url: Some(url.to_owned()),
+            ..Self::new(ActivityType::Streaming, name)
+        }
+    }
+
+    /// "Listening to {name}"
+    pub fn listening(name: &str) -> Self {
+        Self::new(ActivityType::Listening, name)
+    }
+
+    /// "Watching {name}"
+    pub fn watching(name: &str) -> Self {
+        Self::new(ActivityType::Watching, name)
+    }
+
+    /// "Competing in {name}"
+    pub fn competing(name: &str) -> Self {
+        Self::new(ActivityType::Competing, name)
+    }
+
+    /// Custom status with the given text
+    pub fn custom(state: &str) -> Self {
+        Self {
+            state: Some(state.to_owned()),
+            ..Self::new(ActivityType::Custom, "Custom Status")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// [Type](https://discord.com/developers/docs/topics/gateway-events#activity-object-activity-types)
+/// of an activity
+pub enum ActivityType {
+    /// 0: Playing {name}
+    Playing,
+    /// 1: Streaming {name}
+    Streaming,
+    /// 2: Listening to {name}
+    Listening,
+    /// 3: Watching {name}
+    Watching,
+    /// 4: {emoji} {state}
+    Custom,
+    /// 5: Competing in {name}
+    Competing,
+    /// Any other type
+    Other(u8),
+}
+
+impl ActivityType {
+    /// Numeric value of the activity type
+    pub fn code(self) -> u8 {
+        match self {
+            ActivityType::Playing => 0,
+            ActivityType::Streaming => 1,
+            ActivityType::Listening => 2,
+            ActivityType::Watching => 3,
+            ActivityType::Custom => 4,
+            ActivityType::Competing => 5,
+            ActivityType::Other(code) => code,
+        }
+    }
+}
+
+impl From<u8> for ActivityType {
+    fn from(code: u8) -> Self {
+        match code {
+            0 => ActivityType::Playing,
+            1 => ActivityType::Streaming,
+            2 => ActivityType::Listening,
+            3 => ActivityType::Watching,
+            4 => ActivityType::Custom,
+            5 => ActivityType::Competing,
+            code => ActivityType::Other(code),
+        }
+    }
+}
+
+impl Serialize for ActivityType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for ActivityType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u8::deserialize(deserializer).map(Into::into)
+    }
+}
+
+#[derive(Debug, Clone, serde_derive::Deserialize)]
+/// Presence of a user in a guild
+/// ([relevant Discord docs](https://discord.com/developers/docs/topics/gateway-events#presence-update))
+pub struct UserPresence {
+    /// ID of the user
+    #[serde(rename = "user", deserialize_with = "deserialize_user_id")]
+    pub user_id: crate::Snowflake,
+    /// ID of the guild
+    pub guild_id: Option<crate::Snowflake>,
+    /// Online status
+    pub status: Status,
+    /// Activities the user is engaged in
+    #[serde(default)]
+    pub activities: Vec<Activity>,
+}
+
+fn deserialize_user_id<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<crate::Snowflake, D::Error> {
+    #[derive(serde_derive::Deserialize)]
+    struct PartialUser {
+        id: crate::Snowflake,
+    }
+
+    PartialUser::deserialize(deserializer).map(|user| user.id)
+}
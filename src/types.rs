@@ -15,8 +15,10 @@ pub struct Message {
     pub author: User,
     /// Text content of the message
     pub content: String,
-    timestamp: String,
-    edited_timestamp: Option<String>,
+    /// When this message was sent
+    pub timestamp: String,
+    /// When this message was last edited, if ever
+    pub edited_timestamp: Option<String>,
     /// Whether this is a TTS message
     pub tts: bool,
     /// Whether this message mentions everyone
@@ -27,6 +29,32 @@ pub struct Message {
     pub mention_roles: Vec<Snowflake>,
 }
 
+#[derive(Debug, Deserialize)]
+/// Information for connecting to the gateway, returned by `/gateway/bot`
+/// ([relevant Discord docs](https://discord.com/developers/docs/topics/gateway#get-gateway-bot))
+pub struct GatewayBotInfo {
+    /// WebSocket URL to connect to
+    pub url: String,
+    /// Recommended number of shards
+    pub shards: u64,
+    /// Limits on starting new sessions
+    pub session_start_limit: SessionStartLimit,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+/// Limits on how many sessions may be started
+/// ([relevant Discord docs](https://discord.com/developers/docs/topics/gateway#session-start-limit-object))
+pub struct SessionStartLimit {
+    /// Total number of session starts allowed per reset period
+    pub total: u64,
+    /// Number of session starts remaining
+    pub remaining: u64,
+    /// Milliseconds until the limit resets
+    pub reset_after: u64,
+    /// Number of shards that may identify at the same time
+    pub max_concurrency: u64,
+}
+
 #[derive(Debug, Deserialize)]
 /// Data about a Discord User. ([relevant Discord docs](https://discordapp.com/developers/docs/resources/user#user-object))
 pub struct User {
@@ -39,3 +67,143 @@ pub struct User {
     /// User's [avatar hash](https://discordapp.com/developers/docs/reference#image-formatting)
     pub avatar: Option<String>,
 }
+
+#[derive(Debug, Deserialize)]
+/// Member of a guild
+/// ([relevant Discord docs](https://discord.com/developers/docs/resources/guild#guild-member-object))
+pub struct Member {
+    /// User info for the member
+    pub user: User,
+    /// Nickname in the guild, if set
+    pub nick: Option<String>,
+    /// IDs of roles assigned to the member
+    pub roles: Vec<Snowflake>,
+    /// When the member joined the guild
+    pub joined_at: Option<String>,
+    /// When the member started boosting the guild, if they are
+    pub premium_since: Option<String>,
+    /// Whether the member is deafened in voice channels
+    #[serde(default)]
+    pub deaf: bool,
+    /// Whether the member is muted in voice channels
+    #[serde(default)]
+    pub mute: bool,
+    /// Guild-specific [avatar hash](https://discord.com/developers/docs/reference#image-formatting)
+    pub avatar: Option<String>,
+    /// Whether the member has not yet passed the guild's membership screening
+    #[serde(default)]
+    pub pending: bool,
+    /// When the member's timeout expires, if they have been timed out
+    pub communication_disabled_until: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+/// Represents a guild, also known as a server
+/// ([relevant Discord docs](https://discord.com/developers/docs/resources/guild#guild-object))
+pub struct Guild {
+    /// Guild ID
+    pub id: Snowflake,
+    /// Guild name
+    pub name: String,
+    /// Guild's [icon hash](https://discord.com/developers/docs/reference#image-formatting)
+    pub icon: Option<String>,
+    /// ID of the guild owner
+    pub owner_id: Snowflake,
+    /// Roles in the guild
+    pub roles: Vec<Role>,
+    /// Custom emojis in the guild
+    pub emojis: Vec<Emoji>,
+    /// When the bot joined the guild, only sent in GUILD_CREATE
+    pub joined_at: Option<String>,
+    /// Whether the guild is considered large, only sent in GUILD_CREATE
+    pub large: Option<bool>,
+    /// Total number of members, only sent in GUILD_CREATE
+    pub member_count: Option<u64>,
+    /// Members of the guild, only sent in GUILD_CREATE and limited for large guilds
+    #[serde(default)]
+    pub members: Vec<Member>,
+    /// Channels in the guild, only sent in GUILD_CREATE
+    #[serde(default)]
+    pub channels: Vec<Channel>,
+    /// Whether the guild is unavailable due to an outage
+    #[serde(default)]
+    pub unavailable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+/// Guild that is unavailable or was removed, sent in READY and GUILD_DELETE
+/// ([relevant Discord docs](https://discord.com/developers/docs/resources/guild#unavailable-guild-object))
+pub struct UnavailableGuild {
+    /// Guild ID
+    pub id: Snowflake,
+    /// Whether the guild is unavailable due to an outage, rather than the bot being removed from it
+    #[serde(default)]
+    pub unavailable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+/// Role in a guild
+/// ([relevant Discord docs](https://discord.com/developers/docs/topics/permissions#role-object))
+pub struct Role {
+    /// Role ID
+    pub id: Snowflake,
+    /// Role name
+    pub name: String,
+    /// RGB color value, 0 for no color
+    pub color: u32,
+    /// Whether members with this role are displayed separately
+    pub hoist: bool,
+    /// Position in the role list
+    pub position: i64,
+    /// Permission bit set
+    pub permissions: String,
+    /// Whether the role is managed by an integration
+    pub managed: bool,
+    /// Whether the role can be mentioned
+    pub mentionable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+/// Custom emoji
+/// ([relevant Discord docs](https://discord.com/developers/docs/resources/emoji#emoji-object))
+pub struct Emoji {
+    /// Emoji ID
+    pub id: Option<Snowflake>,
+    /// Emoji name, may be missing for deleted emojis
+    pub name: Option<String>,
+    /// IDs of roles allowed to use the emoji, empty if unrestricted
+    #[serde(default)]
+    pub roles: Vec<Snowflake>,
+    /// Whether the emoji is animated
+    #[serde(default)]
+    pub animated: bool,
+    /// Whether the emoji is managed by an integration
+    #[serde(default)]
+    pub managed: bool,
+    /// Whether the emoji can currently be used
+    pub available: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+/// Channel in a guild
+/// ([relevant Discord docs](https://discord.com/developers/docs/resources/channel#channel-object))
+pub struct Channel {
+    /// Channel ID
+    pub id: Snowflake,
+    /// [Channel type](https://discord.com/developers/docs/resources/channel#channel-object-channel-types)
+    #[serde(rename = "type")]
+    pub kind: u8,
+    /// ID of the guild, missing in GUILD_CREATE
+    pub guild_id: Option<Snowflake>,
+    /// Position in the channel list
+    pub position: Option<i64>,
+    /// Channel name
+    pub name: Option<String>,
+    /// Channel topic
+    pub topic: Option<String>,
+    /// ID of the parent category, or of the parent channel for threads
+    pub parent_id: Option<Snowflake>,
+    /// Whether the channel is NSFW
+    #[serde(default)]
+    pub nsfw: bool,
+}
@@ -0,0 +1,212 @@
+use common::{accept, recv, send};
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+
+mod common;
+
+fn client(url: &str) -> noob::Client {
+    noob::ClientBuilder::new("token")
+        .with_tls(false)
+        .with_gateway_url(url)
+        .build()
+}
+
+#[tokio::test]
+async fn commands_fail_after_fatal_close() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        let mut socket = accept(&listener).await;
+        recv(&mut socket).await;
+        let _ = socket
+            .send(tokio_tungstenite::tungstenite::Message::Close(Some(
+                CloseFrame {
+                    code: 4004.into(),
+                    reason: "Authentication failed".into(),
+                },
+            )))
+            .await;
+        // keep the listener alive so no reconnect attempt can succeed
+        std::future::pending::<()>().await;
+    });
+
+    let mut gateway = client(&url).connect_gateway().await.unwrap();
+    let handle = gateway.handle();
+    let events: Vec<_> = tokio::time::timeout(Duration::from_secs(5), async {
+        let mut events = Vec::new();
+        while let Some(event) = gateway.next().await {
+            events.push(event);
+        }
+        events
+    })
+    .await
+    .unwrap();
+    assert!(matches!(
+        events.last(),
+        Some(Err(noob::Error::GatewayClosed {
+            code: noob::CloseCode::AuthenticationFailed,
+            ..
+        }))
+    ));
+
+    let res = tokio::time::timeout(Duration::from_secs(3), handle.send_command(1, ()))
+        .await
+        .unwrap();
+    assert!(matches!(res, Err(noob::Error::GatewayConnectionClosed)));
+    let res = tokio::time::timeout(
+        Duration::from_secs(3),
+        handle.update_presence(noob::Presence::default()),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(res, Err(noob::Error::GatewayConnectionClosed)));
+}
+
+#[tokio::test]
+async fn queued_commands_fail_when_dropped() {
+    // never accepted, so the connection stays pending and the command stays queued
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+
+    let gateway = client(&url).connect_gateway().await.unwrap();
+    let handle = gateway.handle();
+    let queued = tokio::spawn({
+        let handle = handle.clone();
+        async move { handle.send_command(1, ()).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    drop(gateway);
+
+    let res = tokio::time::timeout(Duration::from_secs(3), queued)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(res, Err(noob::Error::GatewayConnectionClosed)));
+    let res = tokio::time::timeout(Duration::from_secs(3), handle.send_command(1, ()))
+        .await
+        .unwrap();
+    assert!(matches!(res, Err(noob::Error::GatewayConnectionClosed)));
+}
+
+#[tokio::test]
+async fn member_requests_fail_after_fatal_close() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        let mut socket = accept(&listener).await;
+        recv(&mut socket).await;
+        // skip the heartbeat sent right after identifying
+        let mut request = recv(&mut socket).await;
+        while request["op"] == 1 {
+            request = recv(&mut socket).await;
+        }
+        assert_eq!(request["op"], 8);
+        let _ = socket
+            .send(tokio_tungstenite::tungstenite::Message::Close(Some(
+                CloseFrame {
+                    code: 4004.into(),
+                    reason: "Authentication failed".into(),
+                },
+            )))
+            .await;
+        std::future::pending::<()>().await;
+    });
+
+    let mut gateway = client(&url).connect_gateway().await.unwrap();
+    let handle = gateway.handle();
+    let request = tokio::spawn(async move {
+        handle
+            .request_guild_members(
+                "1",
+                noob::MemberQuery::Prefix {
+                    query: "",
+                    limit: 0,
+                },
+                false,
+            )
+            .await
+    });
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while gateway.next().await.is_some() {}
+    })
+    .await
+    .unwrap();
+
+    let res = tokio::time::timeout(Duration::from_secs(3), request)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(res, Err(noob::Error::GatewayConnectionClosed)));
+}
+
+#[tokio::test]
+async fn member_requests_queued_before_identify_are_kept() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        let mut socket = accept(&listener).await;
+        assert_eq!(recv(&mut socket).await["op"], 2);
+        let mut request = recv(&mut socket).await;
+        while request["op"] == 1 {
+            request = recv(&mut socket).await;
+        }
+        assert_eq!(request["op"], 8);
+        send(
+            &mut socket,
+            serde_json::json!({
+                "op": 0,
+                "s": 1,
+                "t": "GUILD_MEMBERS_CHUNK",
+                "d": {
+                    "guild_id": "1",
+                    "members": [{
+                        "user": {
+                            "id": "2",
+                            "username": "member",
+                            "discriminator": "0",
+                            "avatar": null,
+                        },
+                        "roles": [],
+                    }],
+                    "chunk_index": 0,
+                    "chunk_count": 1,
+                    "nonce": request["d"]["nonce"],
+                },
+            }),
+        )
+        .await;
+        std::future::pending::<()>().await;
+    });
+
+    let mut gateway = client(&url).connect_gateway().await.unwrap();
+    let handle = gateway.handle();
+    let mut request = tokio::spawn(async move {
+        handle
+            .request_guild_members("1", noob::MemberQuery::UserIds(&["2".to_owned()]), false)
+            .await
+    });
+    // queued before the gateway is polled, so before the first identify
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let res = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            tokio::select! {
+                res = &mut request => return res.unwrap(),
+                event = gateway.next() => {
+                    if let Some(Ok(noob::Event::Unknown { name, .. })) = event {
+                        panic!("Unexpected {} event", name);
+                    }
+                }
+            }
+        }
+    })
+    .await
+    .unwrap();
+    let members = res.unwrap();
+    assert_eq!(members.members.len(), 1);
+    assert_eq!(members.members[0].user.id, "2");
+}
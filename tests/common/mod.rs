@@ -0,0 +1,33 @@
+#![allow(dead_code)]
+
+use futures::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message;
+
+pub type Socket = tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>;
+
+pub async fn accept(listener: &tokio::net::TcpListener) -> Socket {
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+    send(
+        &mut socket,
+        serde_json::json!({"op": 10, "d": {"heartbeat_interval": 45000}}),
+    )
+    .await;
+    socket
+}
+
+pub async fn send(socket: &mut Socket, payload: serde_json::Value) {
+    socket
+        .send(Message::Text(payload.to_string()))
+        .await
+        .unwrap();
+}
+
+pub async fn recv(socket: &mut Socket) -> serde_json::Value {
+    loop {
+        match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+            _ => continue,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use common::{accept, recv, send};
+use futures::StreamExt;
+use std::time::Duration;
+
+mod common;
+
+#[tokio::test]
+async fn resumes_after_reconnect_request() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+
+    let server = tokio::spawn({
+        let url = url.clone();
+        async move {
+            let mut socket = accept(&listener).await;
+            let identify = recv(&mut socket).await;
+            assert_eq!(identify["op"], 2);
+            send(
+                &mut socket,
+                serde_json::json!({
+                    "op": 0,
+                    "t": "READY",
+                    "s": 1,
+                    "d": {
+                        "user": {
+                            "id": "1",
+                            "username": "bot",
+                            "discriminator": "0",
+                            "avatar": null,
+                            "bot": true,
+                            "mfa_enabled": false,
+                            "verified": true,
+                        },
+                        "session_id": "session",
+                        "resume_gateway_url": url,
+                        "guilds": [],
+                    },
+                }),
+            )
+            .await;
+            send(&mut socket, serde_json::json!({"op": 7, "d": null})).await;
+
+            let mut socket = accept(&listener).await;
+            recv(&mut socket).await
+        }
+    });
+
+    let client = noob::ClientBuilder::new("token")
+        .with_tls(false)
+        .with_gateway_url(&url)
+        .build();
+    let mut gateway = client.connect_gateway().await.unwrap();
+
+    let ready = tokio::time::timeout(Duration::from_secs(5), gateway.next())
+        .await
+        .unwrap();
+    match ready {
+        Some(Ok(noob::Event::Ready(data))) => assert_eq!(data.session_id, "session"),
+        other => panic!("Expected READY, got {:?}", other),
+    }
+
+    let resume = tokio::select! {
+        resume = server => resume.unwrap(),
+        _ = async { while gateway.next().await.is_some() {} } => panic!("Gateway ended"),
+        _ = tokio::time::sleep(Duration::from_secs(5)) => panic!("Timed out waiting for resume"),
+    };
+    assert_eq!(resume["op"], 6);
+    assert_eq!(resume["d"]["token"], "Bot token");
+    assert_eq!(resume["d"]["session_id"], "session");
+    assert_eq!(resume["d"]["seq"], 1);
+}